@@ -0,0 +1,107 @@
+use std::cmp;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::Duration;
+
+use date::Date;
+use event::Event;
+use errors::EventError;
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alarm {
+    pub action: Action,
+    pub trigger: Trigger,
+    pub repeat: u32,
+    #[serde(with = "::date::duration")]
+    pub interval: Duration,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    Audio,
+    Display,
+    Email,
+    Other(String),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Trigger {
+    // Relative to the start of the event, or to its end if related_end
+    Relative {
+        #[serde(with = "::date::duration")]
+        offset: Duration,
+        related_end: bool,
+    },
+    Absolute(Date),
+}
+
+impl Alarm {
+    pub fn new() -> Alarm {
+        Alarm {
+            action: Action::Display,
+            trigger: Trigger::Relative {
+                offset: Duration::zero(),
+                related_end: false,
+            },
+            repeat: 0,
+            interval: Duration::zero(),
+            description: "".to_string(),
+        }
+    }
+
+    // When the alarm goes off for this occurrence of the event, repetitions
+    // included
+    pub fn times(&self, event: &Event) -> Vec<Date> {
+        let first = match self.trigger {
+            Trigger::Relative { offset, related_end: false } => event.start + offset,
+            Trigger::Relative { offset, related_end: true } => event.end_date() + offset,
+            Trigger::Absolute(date) => date,
+        };
+        (0..=self.repeat as i32)
+            .map(|i| first + self.interval * i)
+            .collect()
+    }
+
+    // How far from the start or the end of the event the alarm goes off
+    pub fn reach(&self, event: &Event) -> Duration {
+        let (start, end) = (event.start, event.end_date());
+        self.times(event)
+            .into_iter()
+            .map(|at| cmp::max((at - start).abs(), (at - end).abs()))
+            .max()
+            .unwrap_or_else(Duration::zero)
+    }
+}
+
+impl Default for Alarm {
+    fn default() -> Self {
+        Alarm::new()
+    }
+}
+
+impl FromStr for Action {
+    type Err = EventError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "AUDIO" => Action::Audio,
+            "DISPLAY" => Action::Display,
+            "EMAIL" => Action::Email,
+            _ => Action::Other(s.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Action::Audio => f.write_str("AUDIO"),
+            Action::Display => f.write_str("DISPLAY"),
+            Action::Email => f.write_str("EMAIL"),
+            Action::Other(ref action) => f.write_str(action),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt;
+
+// Unknown ROLE and PARTSTAT values are taken as the defaults, as RFC 5545
+// asks for them.
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Organizer {
+    pub address: String,
+    pub name: String,
+    // the parameters other than CN, like SENT-BY
+    pub params: Vec<(String, Vec<String>)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attendee {
+    pub address: String,
+    pub name: String,
+    pub role: Role,
+    pub partstat: PartStat,
+    pub rsvp: bool,
+    // the parameters not above, like CUTYPE or DELEGATED-FROM
+    pub params: Vec<(String, Vec<String>)>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Role {
+    Chair,
+    Required,
+    Optional,
+    NonParticipant,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PartStat {
+    NeedsAction,
+    Accepted,
+    Declined,
+    Tentative,
+    Delegated,
+}
+
+impl Organizer {
+    pub fn new(address: &str) -> Organizer {
+        Organizer {
+            address: address.to_string(),
+            name: "".to_string(),
+            params: vec![],
+        }
+    }
+}
+
+impl Attendee {
+    pub fn new(address: &str) -> Attendee {
+        Attendee {
+            address: address.to_string(),
+            name: "".to_string(),
+            role: Role::Required,
+            partstat: PartStat::NeedsAction,
+            rsvp: false,
+            params: vec![],
+        }
+    }
+
+    // The address without the mailto: scheme, to compare with emails
+    pub fn email(&self) -> &str {
+        email(&self.address)
+    }
+}
+
+impl From<&str> for Role {
+    fn from(s: &str) -> Role {
+        match s {
+            "CHAIR" => Role::Chair,
+            "OPT-PARTICIPANT" => Role::Optional,
+            "NON-PARTICIPANT" => Role::NonParticipant,
+            _ => Role::Required,
+        }
+    }
+}
+
+impl From<&str> for PartStat {
+    fn from(s: &str) -> PartStat {
+        match s {
+            "ACCEPTED" => PartStat::Accepted,
+            "DECLINED" => PartStat::Declined,
+            "TENTATIVE" => PartStat::Tentative,
+            "DELEGATED" => PartStat::Delegated,
+            _ => PartStat::NeedsAction,
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Role::Chair => "CHAIR",
+            Role::Required => "REQ-PARTICIPANT",
+            Role::Optional => "OPT-PARTICIPANT",
+            Role::NonParticipant => "NON-PARTICIPANT",
+        })
+    }
+}
+
+impl fmt::Display for PartStat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            PartStat::NeedsAction => "NEEDS-ACTION",
+            PartStat::Accepted => "ACCEPTED",
+            PartStat::Declined => "DECLINED",
+            PartStat::Tentative => "TENTATIVE",
+            PartStat::Delegated => "DELEGATED",
+        })
+    }
+}
+
+pub fn email(address: &str) -> &str {
+    match address.get(..7) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("mailto:") => &address[7..],
+        _ => address,
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::UNIX_EPOCH;
+
+use bincode;
+use dirs;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use calendar::Calendar;
+use fetch::Feed;
+
+const CACHE_NAME: &str = "almanac";
+
+// Entries from another version of almanac are not trusted to have the same
+// layout
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Fetched feeds and parsed calendars, stored as bincode. Every parsed
+// calendar is saved with a stamp of the source it comes from and it is only
+// used if the source still has the same stamp.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new() -> Option<Cache> {
+        dirs::cache_dir().map(|dir| Cache::at(&dir.join(CACHE_NAME)))
+    }
+
+    pub fn at(dir: &Path) -> Cache {
+        Cache { dir: dir.to_path_buf() }
+    }
+
+    pub fn feed(&self, url: &str) -> Option<Feed> {
+        let (version, feed): (String, Feed) = self.read(url, "feed")?;
+        if version != VERSION {
+            return None;
+        }
+        Some(feed)
+    }
+
+    pub fn store_feed(&self, url: &str, feed: &Feed) -> io::Result<()> {
+        self.write(url, "feed", &(VERSION, feed))
+    }
+
+    // Calendars parsed in lenient mode are not given to strict callers, they
+    // might be missing the components that failed
+    pub fn calendar(&self, source: &str, stamp: &str, strict: bool) -> Option<Calendar> {
+        let (version, cached_strict, cached_stamp, calendar): (String, bool, String, Calendar) =
+            self.read(source, "cal")?;
+        if version != VERSION || cached_strict != strict || cached_stamp != stamp {
+            return None;
+        }
+        Some(calendar)
+    }
+
+    pub fn store_calendar(&self, source: &str, stamp: &str, strict: bool, calendar: &Calendar) -> io::Result<()> {
+        self.write(source, "cal", &(VERSION, strict, stamp, calendar))
+    }
+
+    // Any error reading the entry makes it a miss
+    fn read<T: DeserializeOwned>(&self, source: &str, extension: &str) -> Option<T> {
+        let file = File::open(self.path(source, extension)).ok()?;
+        bincode::deserialize_from(BufReader::new(file)).ok()
+    }
+
+    // Written to a temporary file of the process and moved in place, so
+    // other almanac running at the same time never read half an entry
+    fn write<T: Serialize>(&self, source: &str, extension: &str, value: &T) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(source, extension);
+        let tmp = path.with_extension(format!("{}.{}.tmp", extension, process::id()));
+        let written = File::create(&tmp).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            bincode::serialize_into(&mut out, value).map_err(io::Error::other)?;
+            out.flush()
+        });
+        match written {
+            Ok(()) => fs::rename(tmp, path),
+            Err(err) => {
+                let _ = fs::remove_file(tmp);
+                Err(err)
+            }
+        }
+    }
+
+    fn path(&self, source: &str, extension: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.{}", hash(source.as_bytes()), extension))
+    }
+}
+
+// The size and modification time of the file
+pub fn file_stamp(path: &str) -> io::Result<String> {
+    let metadata = fs::metadata(path)?;
+    let mtime = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok(format!("{}:{}.{:09}", metadata.len(), mtime.as_secs(), mtime.subsec_nanos()))
+}
+
+// Hashing the body works for feeds without validators too, when the server
+// sends again the same content
+pub fn feed_stamp(feed: &Feed) -> String {
+    format!("{:016x}", hash(&feed.body))
+}
+
+// DefaultHasher might change between rust releases, that only means a miss
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use super::Cache;
+    use calendar::Calendar;
+    use event::Event;
+    use fetch::Feed;
+
+    const ICS: &str = "BEGIN:VCALENDAR
+BEGIN:VEVENT
+UID:meeting
+DTSTART;TZID=Europe/Madrid:20190102T100000
+DTEND;TZID=Europe/Madrid:20190102T110000
+RRULE:FREQ=WEEKLY;COUNT=3
+SUMMARY:meeting
+BEGIN:VALARM
+ACTION:DISPLAY
+TRIGGER:-PT15M
+END:VALARM
+END:VEVENT
+BEGIN:VEVENT
+UID:holiday
+DTSTART;VALUE=DATE:20190106
+DURATION:P1D
+SUMMARY:holiday
+END:VEVENT
+BEGIN:VEVENT
+UID:floating
+DTSTART:20190103T090000
+DTEND:20190103T093000
+SUMMARY:breakfast
+END:VEVENT
+END:VCALENDAR
+";
+
+    #[test]
+    fn calendar() {
+        let dir = env::temp_dir().join(format!("almanac-test-{}", process::id()));
+        let cache = Cache::at(&dir);
+        let calendar = Calendar::parse(ICS.as_bytes()).unwrap();
+        cache.store_calendar("cal.ics", "1", true, &calendar).unwrap();
+
+        let cached = cache.calendar("cal.ics", "1", true).unwrap();
+        let events: Vec<Event> = calendar.iter().collect();
+        assert_eq!(events.len(), 5);
+        assert_eq!(cached.iter().collect::<Vec<Event>>(), events);
+        assert_eq!(cached.iter().next().unwrap().alarms, events[0].alarms);
+
+        assert!(cache.calendar("cal.ics", "2", true).is_none());
+        assert!(cache.calendar("cal.ics", "1", false).is_none());
+        assert!(cache.calendar("other.ics", "1", true).is_none());
+
+        let feed = Feed {
+            body: ICS.as_bytes().to_vec(),
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+        };
+        cache.store_feed("https://example.com/cal.ics", &feed).unwrap();
+        assert_eq!(cache.feed("https://example.com/cal.ics"), Some(feed));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
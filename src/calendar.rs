@@ -1,83 +1,678 @@
-use std::io::BufRead;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::fs::File;
+use std::path::Path;
+use std::slice;
 use std::fmt;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
 use ical::IcalParser;
+use ical::property::Property as IcalProperty;
 use chrono::Duration;
 use itertools::Itertools;
+use rayon::prelude::*;
 
 use date::Date;
-use event::{Event, End};
-use periodic::Periodic;
+use event::{Event, End, Property};
+use alarm::{Alarm, Trigger};
+use attendee::{Attendee, Organizer};
+use todo::Todo;
+use journal::Journal;
+use freebusy::{FreeBusy, Busy, BusyType};
+use periodic::{Periodic, Rdate, Override};
+use rrule::Rrule;
+use timezone::{Timezone, Timezones};
 use errors::EventError;
+use writer::{Writer, unescape};
+use vdir;
 
+// How far rules that repeat forever are expanded in an open ended window
+const UNBOUNDED_DAYS: i64 = 366;
+
+#[derive(Serialize, Deserialize)]
 pub struct Calendar {
     single: Vec<Event>,
     periodic: Vec<Periodic>,
+    // overrides of a series that is not in the calendar
+    orphans: Vec<Override>,
+    todos: Vec<Todo>,
+    journals: Vec<Journal>,
+    free_busy: Vec<FreeBusy>,
+    timezones: Timezones,
+    // not kept in the cache, they are reported when the calendar is parsed
+    #[serde(skip)]
+    warnings: Vec<EventError>,
+}
+
+// In lenient mode bad properties are skipped, and the component with them
+// if they are needed to place it in time, instead of failing the parse.
+struct Warnings {
+    strict: bool,
+    list: Vec<EventError>,
+}
+
+impl Warnings {
+    fn report(&mut self, err: EventError) -> Result<(), EventError> {
+        if self.strict {
+            return Err(err);
+        }
+        self.list.push(err);
+        Ok(())
+    }
 }
 
 impl Calendar {
+    pub fn new() -> Calendar {
+        Calendar {
+            single: vec![],
+            periodic: vec![],
+            orphans: vec![],
+            todos: vec![],
+            journals: vec![],
+            free_busy: vec![],
+            timezones: Timezones::new(),
+            warnings: vec![],
+        }
+    }
+
     pub fn parse<B: BufRead>(buf: B) -> Result<Self, EventError> {
+        Calendar::parse_with(buf, true)
+    }
+
+    pub fn parse_lenient<B: BufRead>(buf: B) -> Result<Self, EventError> {
+        Calendar::parse_with(buf, false)
+    }
+
+    // A vdir: a directory with an item in each .ics file, and the
+    // calendars in its subdirectories if recursive
+    pub fn parse_dir(path: &Path, recursive: bool) -> Result<Self, EventError> {
+        Calendar::parse_dir_with(path, recursive, true)
+    }
+
+    // Files that fail to parse are skipped with a warning too
+    pub fn parse_dir_lenient(path: &Path, recursive: bool) -> Result<Self, EventError> {
+        Calendar::parse_dir_with(path, recursive, false)
+    }
+
+    fn parse_dir_with(path: &Path, recursive: bool, strict: bool) -> Result<Self, EventError> {
+        let items = vdir::items(path, recursive)?;
+        let parsed: Vec<(String, Result<Calendar, EventError>)> = items
+            .par_iter()
+            .map(|item| {
+                let name = item.strip_prefix(path).unwrap_or(item).display().to_string();
+                let calendar = File::open(item)
+                    .map_err(EventError::from)
+                    .and_then(|file| Calendar::parse_with(BufReader::new(file), strict));
+                (name, calendar)
+            })
+            .collect();
+
+        let mut calendar = Calendar::new();
+        for (name, item) in parsed {
+            match item {
+                Ok(mut item) => {
+                    item.warnings = item.warnings.into_iter().map(|w| w.in_file(&name)).collect();
+                    calendar.append(item);
+                }
+                Err(err) if !strict => calendar.warnings.push(err.in_file(&name)),
+                Err(err) => return Err(err.in_file(&name)),
+            }
+        }
+        calendar.sort();
+        Ok(calendar)
+    }
+
+    fn parse_with<B: BufRead>(buf: B, strict: bool) -> Result<Self, EventError> {
         let reader = IcalParser::new(buf);
         let mut single = Vec::new();
         let mut periodic = Vec::new();
+        let mut overrides = Vec::new();
+        let mut todos = Vec::new();
+        let mut journals = Vec::new();
+        let mut free_busy = Vec::new();
+        let mut timezones = Timezones::new();
+        let mut warnings = Warnings { strict, list: vec![] };
 
         for line in reader {
-            for ev in line?.events {
+            let ical = line?;
+            for tz in &ical.timezones {
+                match Timezone::parse(tz) {
+                    Ok(timezone) => timezones.insert(timezone),
+                    Err(err) => warnings.report(err)?,
+                }
+            }
+
+            for ev in ical.events {
                 let mut event = Event::new();
-                let mut maybe_periodic = None;
+                let mut start_zone = String::new();
+                let mut maybe_rrule = None;
+                let mut rdates = vec![];
+                let mut exdates = vec![];
+                let mut recurrence_id = None;
+                let mut skip = false;
+
+                let uid = uid(&ev.properties);
 
                 for property in ev.properties {
-                    let value = property.value.unwrap_or("".to_string());
+                    let name = property.name;
+                    let value = property.value.unwrap_or_default();
                     let mut time_zone = "".to_string();
 
-                    let params = property.params.unwrap_or(vec![]);
+                    let params = property.params.unwrap_or_default();
                     for (param, value) in &params {
-                        if param == "TZID" && value.len() > 0 {
+                        if param == "TZID" && !value.is_empty() {
                             time_zone = value[0].clone();
                         }
                     }
 
-                    match property.name.as_ref() {
-                        "SUMMARY" => event.summary = value,
-                        "LOCATION" => event.location = value,
-                        "DESCRIPTION" => event.description = value,
-                        "STATUS" => event.status = value.parse()?,
-                        "DTSTART" => event.start = Date::parse(&value, &time_zone)?,
-                        "DTEND" => event.end = End::Date(Date::parse(&value, &time_zone)?),
-                        "DURATION" => event.end = End::Duration(duration(&value)?),
-                        "RRULE" => maybe_periodic = Some(rrule(&value, &params)?),
-                        _ => (),
+                    let parse = || -> Result<(), EventError> {
+                        match name.as_ref() {
+                            "UID" => event.uid = value.clone(),
+                            "SUMMARY" | "LOCATION" | "DESCRIPTION" => {
+                                let text = unescape(&value);
+                                match name.as_ref() {
+                                    "SUMMARY" => event.summary = text,
+                                    "LOCATION" => event.location = text,
+                                    _ => event.description = text,
+                                }
+                                event.text_params.retain(|(property, _)| *property != name);
+                                if !params.is_empty() {
+                                    event.text_params.push((name.clone(), params.clone()));
+                                }
+                            }
+                            "ORGANIZER" => {
+                                let mut organizer = Organizer::new(&value);
+                                organizer.name = param(&params, "CN").to_string();
+                                organizer.params = other_params(&params, &["CN"]);
+                                event.organizer = Some(organizer);
+                            }
+                            "ATTENDEE" => {
+                                let mut attendee = Attendee::new(&value);
+                                attendee.name = param(&params, "CN").to_string();
+                                attendee.role = param(&params, "ROLE").into();
+                                attendee.partstat = param(&params, "PARTSTAT").into();
+                                attendee.rsvp = param(&params, "RSVP") == "TRUE";
+                                attendee.params = other_params(&params, &["CN", "ROLE", "PARTSTAT", "RSVP"]);
+                                event.attendees.push(attendee);
+                            }
+                            "STATUS" if strict => event.status = Some(value.parse()?),
+                            "STATUS" => event.status = Some(value.to_uppercase().parse()?),
+                            "CATEGORIES" => event.categories.extend(split_list(&value)),
+                            "URL" => event.url = value.clone(),
+                            "CLASS" => event.class = value.as_str().into(),
+                            "TRANSP" => event.transp = value.as_str().into(),
+                            "PRIORITY" => event.priority = value.parse()?,
+                            "SEQUENCE" => event.sequence = value.parse()?,
+                            "CREATED" => event.created = Some(timezones.parse_date(&value, &time_zone)?),
+                            "LAST-MODIFIED" => event.last_modified = Some(timezones.parse_date(&value, &time_zone)?),
+                            "DTSTART" => {
+                                event.start = timezones.parse_date(&value, &time_zone)?;
+                                start_zone = time_zone;
+                            }
+                            "DTEND" => event.end = End::Date(timezones.parse_date(&value, &time_zone)?),
+                            "DURATION" => event.end = End::Duration(duration(&value)?),
+                            "RRULE" => maybe_rrule = Some((value.clone(), params)),
+                            "RDATE" => {
+                                for date in value.split(',') {
+                                    rdates.push(rdate(date, &time_zone, &timezones)?);
+                                }
+                            }
+                            "EXDATE" => {
+                                for date in value.split(',') {
+                                    exdates.push(timezones.parse_date(date, &time_zone)?);
+                                }
+                            }
+                            "RECURRENCE-ID" => {
+                                let this_and_future = params.iter().any(|(param, values)| {
+                                    param == "RANGE" && values.iter().any(|v| v == "THISANDFUTURE")
+                                });
+                                recurrence_id = Some((timezones.parse_date(&value, &time_zone)?, this_and_future));
+                            }
+                            _ => event.properties.push(Property {
+                                name: name.clone(),
+                                params: params.clone(),
+                                value: value.clone(),
+                            }),
+                        };
+                        Ok(())
+                    };
+                    if let Err(err) = parse() {
+                        warnings.report(err.in_property(&name, &value, &uid))?;
+                        skip |= ["DTSTART", "RECURRENCE-ID"].contains(&name.as_str());
+                    }
+                }
+                for al in ev.alarms {
+                    match alarm(al.properties, &timezones) {
+                        Ok(alarm) => event.alarms.push(alarm),
+                        Err(err) => warnings.report(err.in_property("VALARM", "", &uid))?,
+                    }
+                }
+                if skip {
+                    continue;
+                }
+                if let Some((recurrence_id, this_and_future)) = recurrence_id {
+                    overrides.push(Override {
+                        recurrence_id,
+                        this_and_future,
+                        event,
+                    });
+                } else if maybe_rrule.is_some() || !rdates.is_empty() {
+                    let rrule = match maybe_rrule {
+                        Some((value, params)) => match rrule(&value, &params, &start_zone, &timezones) {
+                            Ok(rrule) => Some(rrule),
+                            Err(err) => {
+                                warnings.report(err.in_property("RRULE", &value, &uid))?;
+                                continue;
+                            }
+                        },
+                        None => None,
+                    };
+                    rdates.sort_by_key(|r: &Rdate| r.start);
+                    periodic.push(Periodic {
+                        event,
+                        rrule,
+                        rdates,
+                        exdates,
+                        overrides: vec![],
+                        timezone: timezones.get(&start_zone).cloned(),
+                    });
+                } else {
+                    single.push(event);
+                }
+            }
+
+            for td in ical.todos {
+                let mut todo = Todo::new();
+                let uid = uid(&td.properties);
+
+                for property in td.properties {
+                    let name = property.name;
+                    let value = property.value.unwrap_or_default();
+                    let params = property.params.unwrap_or_default();
+                    let time_zone = tzid(&params);
+
+                    let mut parse = || -> Result<(), EventError> {
+                        match name.as_ref() {
+                            "UID" => todo.uid = value.clone(),
+                            "SUMMARY" => todo.summary = unescape(&value),
+                            "DESCRIPTION" => todo.description = unescape(&value),
+                            "STATUS" if strict => todo.status = Some(value.parse()?),
+                            "STATUS" => todo.status = Some(value.to_uppercase().parse()?),
+                            "DTSTART" => todo.start = Some(timezones.parse_date(&value, &time_zone)?),
+                            "DUE" => todo.due = Some(timezones.parse_date(&value, &time_zone)?),
+                            "COMPLETED" => todo.completed = Some(timezones.parse_date(&value, &time_zone)?),
+                            "PRIORITY" => todo.priority = value.parse()?,
+                            "PERCENT-COMPLETE" => todo.percent_complete = value.parse()?,
+                            _ => todo.properties.push(Property {
+                                name: name.clone(),
+                                params: params.clone(),
+                                value: value.clone(),
+                            }),
+                        };
+                        Ok(())
                     };
+                    if let Err(err) = parse() {
+                        warnings.report(err.in_property(&name, &value, &uid))?;
+                    }
                 }
-                match maybe_periodic {
-                    Some(mut p) => {
-                        p.event = event;
-                        periodic.push(p);
+                for al in td.alarms {
+                    match alarm(al.properties, &timezones) {
+                        Ok(alarm) => todo.alarms.push(alarm),
+                        Err(err) => warnings.report(err.in_property("VALARM", "", &uid))?,
                     }
-                    None => single.push(event),
                 }
+                todos.push(todo);
+            }
+
+            for jr in ical.journals {
+                let mut journal = Journal::new();
+                let uid = uid(&jr.properties);
+
+                for property in jr.properties {
+                    let name = property.name;
+                    let value = property.value.unwrap_or_default();
+                    let params = property.params.unwrap_or_default();
+                    let time_zone = tzid(&params);
+
+                    let mut parse = || -> Result<(), EventError> {
+                        match name.as_ref() {
+                            "UID" => journal.uid = value.clone(),
+                            "SUMMARY" => journal.summary = unescape(&value),
+                            "DESCRIPTION" if !journal.description.is_empty() => {
+                                journal.description = format!("{}\n{}", journal.description, unescape(&value))
+                            }
+                            "DESCRIPTION" => journal.description = unescape(&value),
+                            "DTSTART" => journal.start = Some(timezones.parse_date(&value, &time_zone)?),
+                            _ => journal.properties.push(Property {
+                                name: name.clone(),
+                                params: params.clone(),
+                                value: value.clone(),
+                            }),
+                        };
+                        Ok(())
+                    };
+                    if let Err(err) = parse() {
+                        warnings.report(err.in_property(&name, &value, &uid))?;
+                    }
+                }
+                journals.push(journal);
+            }
+
+            for fb in ical.free_busys {
+                let mut fbusy = FreeBusy::new();
+                let uid = uid(&fb.properties);
+
+                for property in fb.properties {
+                    let name = property.name;
+                    let value = property.value.unwrap_or_default();
+                    let params = property.params.unwrap_or_default();
+                    let time_zone = tzid(&params);
+
+                    let mut parse = || -> Result<(), EventError> {
+                        match name.as_ref() {
+                            "UID" => fbusy.uid = value.clone(),
+                            "ORGANIZER" => fbusy.organizer = value.clone(),
+                            "DTSTART" => fbusy.start = Some(timezones.parse_date(&value, &time_zone)?),
+                            "DTEND" => fbusy.end = Some(timezones.parse_date(&value, &time_zone)?),
+                            "FREEBUSY" => {
+                                let kind = params
+                                    .iter()
+                                    .find(|(param, _)| param == "FBTYPE")
+                                    .and_then(|(_, values)| values.first())
+                                    .map_or(BusyType::Busy, |kind| kind.as_str().into());
+                                for period in value.split(',') {
+                                    let period = rdate(period, &time_zone, &timezones)?;
+                                    let end = match period.end {
+                                        Some(End::Date(end)) => end,
+                                        Some(End::Duration(duration)) => period.start + duration,
+                                        None => return Err(EventError::DateError(value.clone())),
+                                    };
+                                    fbusy.periods.push(Busy {
+                                        start: period.start,
+                                        end,
+                                        kind,
+                                    });
+                                }
+                            }
+                            _ => (),
+                        };
+                        Ok(())
+                    };
+                    if let Err(err) = parse() {
+                        warnings.report(err.in_property(&name, &value, &uid))?;
+                    }
+                }
+                fbusy.periods.sort();
+                free_busy.push(fbusy);
+            }
+        }
+
+        let mut calendar = Calendar {
+            single,
+            periodic,
+            orphans: vec![],
+            todos,
+            journals,
+            free_busy,
+            timezones,
+            warnings: warnings.list,
+        };
+        calendar.attach(overrides);
+        calendar.sort();
+        Ok(calendar)
+    }
+
+    pub fn todos(&self) -> &[Todo] {
+        &self.todos
+    }
+
+    pub fn journals<'a>(&'a self) -> impl Iterator<Item = &'a Journal> + 'a {
+        self.journals.iter()
+    }
+
+    pub fn free_busy(&self) -> &[FreeBusy] {
+        &self.free_busy
+    }
+
+    // Busy periods of all the VFREEBUSY components in order
+    pub fn busy<'a>(&'a self) -> impl Iterator<Item = Busy> + 'a {
+        self.free_busy
+            .iter()
+            .map(|fb| fb.periods.iter().cloned())
+            .kmerge()
+    }
+
+    pub fn write<W: Write>(&self, out: W) -> io::Result<()> {
+        Calendar::write_all(slice::from_ref(self), out).map(|_| ())
+    }
+
+    // The calendars as a single one. The dates in zones of the tz database
+    // have their VTIMEZONE first, they are found writing everything once
+    // to nowhere.
+    pub fn write_all<W: Write>(calendars: &[Calendar], out: W) -> io::Result<W> {
+        let mut probe = Writer::new(io::sink());
+        for calendar in calendars {
+            calendar.write_components(&mut probe)?;
+        }
+        let defined: HashSet<&str> = calendars
+            .iter()
+            .flat_map(|c| c.timezones.iter().map(|tz| tz.tzid.as_str()))
+            .collect();
+
+        let mut writer = Writer::new(out);
+        writer.begin("VCALENDAR")?;
+        writer.property("VERSION", &[], "2.0")?;
+        writer.property("PRODID", &[], "-//almanac//almanac//EN")?;
+        for timezone in probe.used_timezones().iter().filter(|tz| !defined.contains(tz.tzid.as_str())) {
+            timezone.write(&mut writer)?;
+        }
+        for calendar in calendars {
+            calendar.write_components(&mut writer)?;
+        }
+        writer.end("VCALENDAR")?;
+        Ok(writer.into_inner())
+    }
+
+    fn write_components<W: Write>(&self, writer: &mut Writer<W>) -> io::Result<()> {
+        for timezone in self.timezones.iter().sorted_by(|a, b| a.tzid.cmp(&b.tzid)) {
+            timezone.write(writer)?;
+        }
+        for event in &self.single {
+            writer.event(event)?;
+        }
+        for orphan in &self.orphans {
+            writer.instance(orphan, None)?;
+        }
+        for periodic in &self.periodic {
+            writer.periodic(periodic)?;
+        }
+        for todo in &self.todos {
+            writer.todo(todo)?;
+        }
+        for journal in &self.journals {
+            writer.journal(journal)?;
+        }
+        for free_busy in &self.free_busy {
+            writer.free_busy(free_busy)?;
+        }
+        Ok(())
+    }
+
+    pub fn warnings(&self) -> &[EventError] {
+        &self.warnings
+    }
+
+    // Components are identified by their UID and RECURRENCE-ID. Of the ones
+    // in both calendars the newest version by SEQUENCE and LAST-MODIFIED is
+    // kept, on a tie the one already in self. The overrides are attached to
+    // the master of their UID, whichever calendar it comes from.
+    pub fn merge(&mut self, mut other: Calendar) {
+        let mut overrides = self.take_overrides();
+        {
+            let mut index: HashMap<_, _> = overrides
+                .iter()
+                .enumerate()
+                .filter(|(_, o)| !o.event.uid.is_empty())
+                .map(|(i, o)| ((o.event.uid.clone(), o.recurrence_id.utc()), i))
+                .collect();
+            for o in other.take_overrides() {
+                let key = (o.event.uid.clone(), o.recurrence_id.utc());
+                match index.get(&key) {
+                    Some(&i) if version(&o.event) > version(&overrides[i].event) => overrides[i] = o,
+                    Some(_) => {}
+                    None => {
+                        if !o.event.uid.is_empty() {
+                            index.insert(key, overrides.len());
+                        }
+                        overrides.push(o);
+                    }
+                }
+            }
+        }
+
+        let mut replaced = HashSet::new();
+        let mut kept = HashSet::new();
+        {
+            let mine = self.versions();
+            for (uid, theirs) in other.versions() {
+                match mine.get(uid) {
+                    Some(mine) if theirs > *mine => replaced.insert(uid.to_string()),
+                    Some(_) => kept.insert(uid.to_string()),
+                    None => false,
+                };
             }
         }
 
-        single.sort();
-        Ok(Calendar { single, periodic })
+        self.single.retain(|e| !replaced.contains(&e.uid));
+        self.periodic.retain(|p| !replaced.contains(&p.event.uid));
+        other.single.retain(|e| !kept.contains(&e.uid));
+        other.periodic.retain(|p| !kept.contains(&p.event.uid));
+        self.append(other);
+        self.attach(overrides);
+        self.sort();
+    }
+
+    // Removes the overrides, attached or not
+    fn take_overrides(&mut self) -> Vec<Override> {
+        let mut overrides: Vec<Override> = self.periodic.iter_mut().flat_map(|p| p.overrides.drain(..)).collect();
+        overrides.append(&mut self.orphans);
+        overrides
+    }
+
+    // Attaches each override to the series of its UID, the ones without it
+    // are kept apart
+    fn attach(&mut self, overrides: Vec<Override>) {
+        let uids: HashMap<String, usize> = self
+            .periodic
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| !p.event.uid.is_empty())
+            .map(|(i, p)| (p.event.uid.clone(), i))
+            .collect();
+        for o in overrides {
+            match uids.get(&o.event.uid) {
+                Some(&i) => self.periodic[i].overrides.push(o),
+                None => self.orphans.push(o),
+            }
+        }
+    }
+
+    // Adds all the components of other, leaving them unsorted
+    fn append(&mut self, other: Calendar) {
+        self.single.extend(other.single);
+        self.periodic.extend(other.periodic);
+        self.orphans.extend(other.orphans);
+        self.todos.extend(other.todos);
+        self.journals.extend(other.journals);
+        self.free_busy.extend(other.free_busy);
+        for timezone in other.timezones.iter() {
+            if self.timezones.get(&timezone.tzid).is_none() {
+                self.timezones.insert(timezone.clone());
+            }
+        }
+        self.warnings.extend(other.warnings);
+    }
+
+    fn sort(&mut self) {
+        self.single.sort();
+        self.orphans.sort_by(|a, b| a.event.cmp(&b.event));
+        self.todos.sort();
+        self.journals.sort();
+    }
+
+    // The newest version of the masters of each UID
+    fn versions(&self) -> HashMap<&str, (u32, Option<Date>)> {
+        let mut versions = HashMap::new();
+        let events = self.single.iter().chain(self.periodic.iter().map(|p| &p.event));
+        for event in events.filter(|e| !e.uid.is_empty()) {
+            let version = version(event);
+            let newest = versions.entry(event.uid.as_str()).or_insert(version);
+            if version > *newest {
+                *newest = version;
+            }
+        }
+        versions
+    }
+
+    // The farthest an alarm goes off from its event, the occurrences of a
+    // series are as far as the series
+    pub fn alarm_reach(&self) -> Duration {
+        let overrides = self.periodic.iter().flat_map(|p| &p.overrides).chain(&self.orphans);
+        self.single
+            .iter()
+            .chain(self.periodic.iter().map(|p| &p.event))
+            .chain(overrides.map(|o| &o.event))
+            .flat_map(|e| e.alarms.iter().map(move |a| a.reach(e)))
+            .max()
+            .unwrap_or_else(Duration::zero)
+    }
+
+    pub fn timezone(&self, tzid: &str) -> Option<&Timezone> {
+        self.timezones.get(tzid)
     }
 
     pub fn iter<'a>(&'a self) -> impl Iterator<Item = Event> + 'a {
-        self.single.iter().map(Event::clone).merge(
+        let orphans = self.orphans.iter().map(|o| o.event.clone());
+        self.single.iter().map(Event::clone).merge(orphans).merge(
             self.periodic
                 .iter()
                 .map(|p| p.iter())
                 .kmerge(),
         )
     }
+
+    // The occurrences that overlap [start, end), sorted. With an open ended
+    // window the rules that repeat forever stop a year after now, or they
+    // would never finish.
+    pub fn between<'a>(&'a self, start: Date, end: Date) -> impl Iterator<Item = Event> + 'a {
+        let horizon = cmp::max(start, Date::now()) + Duration::days(UNBOUNDED_DAYS);
+        let count = self.single.partition_point(|e| e.start < end);
+        let single = self.single[..count].iter().filter(move |e| e.overlaps(start, end)).cloned();
+        let orphans = self.orphans.iter().map(|o| &o.event).filter(move |e| e.overlaps(start, end)).cloned();
+        single.merge(orphans).merge(
+            self.periodic
+                .iter()
+                .map(move |p| {
+                    let end = if end == Date::max() && p.is_unbounded() { horizon } else { end };
+                    p.occurrences_between(start, end)
+                })
+                .kmerge(),
+        )
+    }
+}
+
+impl Default for Calendar {
+    fn default() -> Self {
+        Calendar::new()
+    }
 }
 
 impl fmt::Display for Calendar {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for event in &self.single {
+        for event in self.single.iter().chain(self.orphans.iter().map(|o| &o.event)) {
             writeln!(f, "{}", event)?;
         }
-        writeln!(f, "")?;
+        writeln!(f)?;
         for periodic in &self.periodic {
             writeln!(f, "{}", periodic)?;
         }
@@ -85,47 +680,741 @@ impl fmt::Display for Calendar {
     }
 }
 
-fn rrule(value: &str, params: &Vec<(String, Vec<String>)>) -> Result<Periodic, EventError> {
-    let mut periodic = Periodic::new();
+fn version(event: &Event) -> (u32, Option<Date>) {
+    (event.sequence, event.last_modified)
+}
+
+fn uid(properties: &[IcalProperty]) -> String {
+    properties
+        .iter()
+        .find(|p| p.name == "UID")
+        .and_then(|p| p.value.clone())
+        .unwrap_or_default()
+}
+
+// Comma separated TEXT values, the escaped commas are part of the value
+fn split_list(value: &str) -> Vec<String> {
+    let mut list = vec![];
+    let mut item = String::new();
+    let mut escaped = false;
+    for c in value.chars() {
+        match c {
+            ',' if !escaped => list.push(item.split_off(0)),
+            _ => item.push(c),
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    list.push(item);
+    list.into_iter().map(|item| unescape(item.trim())).filter(|item| !item.is_empty()).collect()
+}
+
+fn tzid(params: &[(String, Vec<String>)]) -> String {
+    param(params, "TZID").to_string()
+}
+
+fn param<'a>(params: &'a [(String, Vec<String>)], name: &str) -> &'a str {
+    params
+        .iter()
+        .find(|(param, _)| param == name)
+        .and_then(|(_, values)| values.first())
+        .map(String::as_str)
+        .unwrap_or("")
+}
+
+fn other_params(params: &[(String, Vec<String>)], typed: &[&str]) -> Vec<(String, Vec<String>)> {
+    params
+        .iter()
+        .filter(|(param, _)| !typed.contains(&param.as_str()))
+        .cloned()
+        .collect()
+}
+
+fn alarm(properties: Vec<IcalProperty>, timezones: &Timezones) -> Result<Alarm, EventError> {
+    let mut alarm = Alarm::new();
+    for property in properties {
+        let name = property.name;
+        let value = property.value.unwrap_or_default();
+        let params = property.params.unwrap_or_default();
+
+        let parsed = match name.as_ref() {
+            "ACTION" => value.parse().map(|action| alarm.action = action),
+            "DESCRIPTION" => {
+                alarm.description = unescape(&value);
+                Ok(())
+            }
+            "REPEAT" => value.parse().map(|repeat| alarm.repeat = repeat).map_err(EventError::from),
+            "DURATION" => duration(&value).map(|interval| alarm.interval = interval),
+            "TRIGGER" if param(&params, "VALUE") == "DATE-TIME" => timezones
+                .parse_date(&value, &tzid(&params))
+                .map(|date| alarm.trigger = Trigger::Absolute(date)),
+            "TRIGGER" => duration(&value).map(|offset| {
+                alarm.trigger = Trigger::Relative {
+                    offset,
+                    related_end: param(&params, "RELATED") == "END",
+                }
+            }),
+            _ => Ok(()),
+        };
+        parsed.map_err(|err| err.in_property(&name, &value, ""))?;
+    }
+    Ok(alarm)
+}
+
+fn rrule(
+    value: &str,
+    params: &[(String, Vec<String>)],
+    time_zone: &str,
+    timezones: &Timezones,
+) -> Result<Rrule, EventError> {
+    let mut rrule = Rrule::new();
 
-    for entry in value.split(";") {
-        let p: Vec<&str> = entry.splitn(2, "=").collect();
-        periodic.set_param(p[0], p[1])?;
+    for entry in value.split(';') {
+        let p: Vec<&str> = entry.splitn(2, '=').collect();
+        if p.len() != 2 {
+            continue;
+        }
+        if p[0] == "UNTIL" {
+            rrule.until = Some(timezones.parse_date(p[1], time_zone)?);
+        } else {
+            rrule.set_param(p[0], p[1])?;
+        }
     }
 
     for (param, values) in params {
-        let mut value = "";
-        if values.len() > 0 {
-            value = &values[0];
-        }
-        periodic.set_param(param, value)?;
+        let value = values.first().map(String::as_str).unwrap_or("");
+        rrule.set_param(param, value)?;
     }
 
-    Ok(periodic)
+    Ok(rrule)
 }
 
+fn rdate(value: &str, time_zone: &str, timezones: &Timezones) -> Result<Rdate, EventError> {
+    let mut parts = value.splitn(2, '/');
+    let start = timezones.parse_date(parts.next().unwrap_or(""), time_zone)?;
+    let end = match parts.next() {
+        Some(end) if end.contains('P') => Some(End::Duration(duration(end)?)),
+        Some(end) => Some(End::Date(timezones.parse_date(end, time_zone)?)),
+        None => None,
+    };
+    Ok(Rdate { start, end })
+}
+
+// Longer durations would overflow the dates they are added to
+const MAX_DURATION_DAYS: i64 = 1_000_000;
+
 fn duration(value: &str) -> Result<Duration, EventError> {
-    let mut duration = Duration::seconds(0);
-    let mut acc = "".to_string();
+    let invalid = || EventError::DurationError(value.to_string());
+    let mut seconds: i64 = 0;
+    let mut negative = false;
+    let mut acc = String::new();
     for c in value.chars() {
         match c {
             '0'..='9' => acc.push(c),
-            '-' => duration = -duration,
+            '-' => negative = true,
             'W' | 'H' | 'M' | 'S' | 'D' => {
-                let count = acc.parse()?;
-                acc = "".to_string();
-                let d = match c {
-                    'W' => Duration::weeks(count),
-                    'H' => Duration::hours(count),
-                    'M' => Duration::minutes(count),
-                    'S' => Duration::seconds(count),
-                    'D' => Duration::days(count),
-                    _ => Duration::seconds(0),
+                let count: i64 = acc.parse()?;
+                acc = String::new();
+                let unit = match c {
+                    'W' => 604_800,
+                    'D' => 86_400,
+                    'H' => 3600,
+                    'M' => 60,
+                    _ => 1,
                 };
-                duration = duration + d;
+                seconds = count
+                    .checked_mul(unit)
+                    .and_then(|s| seconds.checked_add(s))
+                    .ok_or_else(invalid)?;
             }
             _ => (),
         }
     }
-    Ok(duration)
+    if seconds > MAX_DURATION_DAYS * 86_400 {
+        return Err(invalid());
+    }
+    Ok(Duration::seconds(if negative { -seconds } else { seconds }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use chrono::Duration;
+
+    use super::Calendar;
+    use date::Date;
+    use event::Event;
+    use attendee::{PartStat, Role};
+    use event::{Class, Transp};
+
+    fn starts(ics: &str, limit: usize) -> Vec<String> {
+        Calendar::parse(ics.as_bytes())
+            .unwrap()
+            .iter()
+            .take(limit)
+            .map(|e| e.start.naive().format("%Y%m%dT%H%M").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn exdate() {
+        let ics = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+DTSTART;TZID=America/New_York:19970902T090000\r
+EXDATE;TZID=America/New_York:19970902T090000\r
+RRULE:FREQ=MONTHLY;BYDAY=FR;BYMONTHDAY=13\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        assert_eq!(starts(ics, 3), vec!["19980213T0900", "19980313T0900", "19981113T0900"]);
+    }
+
+    #[test]
+    fn exdate_list() {
+        let ics = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+DTSTART;TZID=Europe/Madrid:20190102T100000\r
+RRULE:FREQ=DAILY;COUNT=6\r
+EXDATE;TZID=Europe/Madrid:20190103T100000,20190105T100000\r
+EXDATE:20190106T090000Z\r
+EXDATE;VALUE=DATE:20190104\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        assert_eq!(starts(ics, 10), vec!["20190102T1000", "20190107T1000"]);
+    }
+
+    #[test]
+    fn dates_in_vtimezone() {
+        // the days of the occurrences are the ones of the VTIMEZONE, 14
+        // hours ahead of the UTC they are kept in
+        let ics = "BEGIN:VCALENDAR\r
+BEGIN:VTIMEZONE\r
+TZID:Line\r
+BEGIN:STANDARD\r
+DTSTART:19700101T000000\r
+TZOFFSETFROM:+1400\r
+TZOFFSETTO:+1400\r
+END:STANDARD\r
+END:VTIMEZONE\r
+BEGIN:VEVENT\r
+DTSTART;TZID=Line:20190102T003000\r
+RRULE:FREQ=DAILY;UNTIL=20190105\r
+EXDATE;VALUE=DATE:20190103\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        assert_eq!(starts(ics, 10), vec!["20190101T1030", "20190103T1030", "20190104T1030"]);
+    }
+
+    // The tests above in zones far from UTC, the days matched by EXDATE and
+    // UNTIL don't depend on the local time zone
+    #[test]
+    fn dates_in_far_zones() {
+        for tz in &["Pacific/Pago_Pago", "Pacific/Kiritimati", "America/Los_Angeles"] {
+            for test in &["calendar::tests::exdate_list", "calendar::tests::dates_in_vtimezone"] {
+                let output = process::Command::new(env::current_exe().unwrap())
+                    .args(["--exact", test])
+                    .env("TZ", tz)
+                    .output()
+                    .unwrap();
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                assert!(output.status.success() && stdout.contains("1 passed"), "{} in {}", test, tz);
+            }
+        }
+    }
+
+    #[test]
+    fn rdate() {
+        let ics = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+DTSTART:20190102T100000Z\r
+DTEND:20190102T110000Z\r
+RRULE:FREQ=WEEKLY;COUNT=3\r
+RDATE:20190110T100000Z,20190103T120000Z\r
+RDATE;VALUE=PERIOD:20190104T080000Z/PT3H,20190109T100000Z/20190109T100500Z\r
+RDATE:20190116T100000Z\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let calendar = Calendar::parse(ics.as_bytes()).unwrap();
+        let events: Vec<String> = calendar
+            .iter()
+            .map(|e| format!("{}-{}",
+                             e.start.naive().format("%d %H:%M"),
+                             e.end_date().naive().format("%H:%M")))
+            .collect();
+        assert_eq!(events, vec!["02 10:00-11:00", "03 12:00-13:00", "04 08:00-11:00",
+                                "09 10:00-10:05", "10 10:00-11:00",
+                                "16 10:00-11:00"]);
+    }
+
+    #[test]
+    fn rdate_without_rrule() {
+        let ics = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+DTSTART;VALUE=DATE:20190102\r
+RDATE;VALUE=DATE:20190201,20190301\r
+EXDATE;VALUE=DATE:20190201\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        assert_eq!(starts(ics, 10), vec!["20190102T0000", "20190301T0000"]);
+    }
+
+    #[test]
+    fn property_error() {
+        let ics = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+DTSTART:20190102T100000Z\r
+STATUS:MAYBE\r
+UID:meeting\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let err = Calendar::parse(ics.as_bytes()).err().unwrap();
+        assert_eq!(err.to_string(), "STATUS \"MAYBE\" in meeting: invalid status \"MAYBE\"");
+    }
+
+    #[test]
+    fn lenient() {
+        let ics = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+DTSTART:20190102T100000Z\r
+STATUS:confirmed\r
+SUMMARY:repaired\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+DTSTART:20190103T100000Z\r
+STATUS:NEEDS-ACTION\r
+SUMMARY:skipped status\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+DTSTART:2019\r
+SUMMARY:skipped\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+DTSTART:20190104T100000Z\r
+RRULE:FREQ=SOMETIMES\r
+SUMMARY:skipped rule\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        assert!(Calendar::parse(ics.as_bytes()).is_err());
+        let calendar = Calendar::parse_lenient(ics.as_bytes()).unwrap();
+        let summaries: Vec<String> = calendar.iter().map(|e| e.summary).collect();
+        assert_eq!(summaries, vec!["repaired", "skipped status"]);
+        assert_eq!(calendar.warnings().len(), 3);
+    }
+
+    #[test]
+    fn duration_overflow() {
+        let ics = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+DTSTART:20190102T100000Z\r
+DURATION:P99999999999999W\r
+SUMMARY:endless\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+DTSTART:20190103T100000Z\r
+DURATION:PT1H\r
+SUMMARY:alarm\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+TRIGGER:-P99999999999999W\r
+END:VALARM\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let err = Calendar::parse(ics.as_bytes()).err().unwrap();
+        assert!(err.to_string().contains("invalid duration \"P99999999999999W\""), "{}", err);
+        let calendar = Calendar::parse_lenient(ics.as_bytes()).unwrap();
+        assert_eq!(calendar.warnings().len(), 2);
+        assert_eq!(calendar.iter().count(), 2);
+    }
+
+    #[test]
+    fn vdir() {
+        let dir = env::temp_dir().join(format!("almanac-vdir-{}", process::id()));
+        fs::create_dir_all(dir.join("work")).unwrap();
+        let item = |summary: &str, start: &str| {
+            format!("BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nUID:{}\r\nDTSTART:{}\r\n\
+                     SUMMARY:{}\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n", summary, start, summary)
+        };
+        fs::write(dir.join("b.ics"), item("second", "20190102T100000Z")).unwrap();
+        fs::write(dir.join("a.ics"), item("third", "20190103T100000Z")).unwrap();
+        fs::write(dir.join("broken.ics"), item("broken", "2019")).unwrap();
+        fs::write(dir.join(".hidden.ics"), item("hidden", "20190101T100000Z")).unwrap();
+        fs::write(dir.join("notes.txt"), "not an item").unwrap();
+        fs::write(dir.join("work").join("c.ics"), item("first", "20190101T100000Z")).unwrap();
+
+        let err = Calendar::parse_dir(&dir, false).err().unwrap();
+        assert!(err.to_string().starts_with("broken.ics: DTSTART"));
+
+        let calendar = Calendar::parse_dir_lenient(&dir, false).unwrap();
+        let summaries: Vec<String> = calendar.iter().map(|e| e.summary).collect();
+        assert_eq!(summaries, vec!["second", "third"]);
+        assert_eq!(calendar.warnings().len(), 1);
+
+        let calendar = Calendar::parse_dir_lenient(&dir, true).unwrap();
+        let summaries: Vec<String> = calendar.iter().map(|e| e.summary).collect();
+        assert_eq!(summaries, vec!["first", "second", "third"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn todos() {
+        let ics = "BEGIN:VCALENDAR\r
+BEGIN:VTODO\r
+UID:later\r
+DUE;VALUE=DATE:20190110\r
+SUMMARY:later\r
+END:VTODO\r
+BEGIN:VTODO\r
+UID:first\r
+DUE;TZID=Europe/Madrid:20190101T100000\r
+PRIORITY:1\r
+PERCENT-COMPLETE:30\r
+STATUS:IN-PROCESS\r
+SUMMARY:first\r
+END:VTODO\r
+BEGIN:VTODO\r
+UID:done\r
+STATUS:COMPLETED\r
+SUMMARY:done\r
+END:VTODO\r
+END:VCALENDAR\r
+";
+        let calendar = Calendar::parse(ics.as_bytes()).unwrap();
+        let todos: Vec<String> = calendar
+            .todos()
+            .iter()
+            .filter(|t| t.is_open())
+            .map(|t| format!("{} {}", t.summary, t.due.unwrap().naive().format("%d %H:%M")))
+            .collect();
+        assert_eq!(todos, vec!["first 01 10:00", "later 10 00:00"]);
+        assert_eq!(calendar.todos()[0].percent_complete, 30);
+    }
+
+    #[test]
+    fn journals_and_free_busy() {
+        let ics = "BEGIN:VCALENDAR\r
+BEGIN:VJOURNAL\r
+UID:notes\r
+DTSTART;VALUE=DATE:20190103\r
+SUMMARY:notes\r
+DESCRIPTION:first\r
+DESCRIPTION:second\r
+END:VJOURNAL\r
+BEGIN:VJOURNAL\r
+SUMMARY:undated\r
+END:VJOURNAL\r
+BEGIN:VFREEBUSY\r
+UID:room\r
+ORGANIZER:mailto:room@example.com\r
+DTSTART:20190101T000000Z\r
+DTEND:20190108T000000Z\r
+FREEBUSY;FBTYPE=BUSY-UNAVAILABLE:20190102T090000Z/PT8H\r
+FREEBUSY:20190101T100000Z/20190101T110000Z,20190103T100000Z/PT30M\r
+FREEBUSY;FBTYPE=X-OUT-OF-OFFICE:20190104T100000Z/PT1H\r
+END:VFREEBUSY\r
+END:VCALENDAR\r
+";
+        let calendar = Calendar::parse(ics.as_bytes()).unwrap();
+        let journals: Vec<String> = calendar
+            .journals()
+            .map(|j| format!("{} {}", j.summary, j.description))
+            .collect();
+        assert_eq!(journals, vec!["notes first\nsecond", "undated "]);
+        let busy: Vec<String> = calendar
+            .busy()
+            .map(|b| format!("{:?} {}-{}", b.kind, b.start.naive().format("%d %H:%M"), b.end.naive().format("%H:%M")))
+            .collect();
+        // an unknown FBTYPE is busy
+        assert_eq!(busy, vec!["Busy 01 10:00-11:00", "Unavailable 02 09:00-17:00", "Busy 03 10:00-10:30",
+                              "Busy 04 10:00-11:00"]);
+    }
+
+    #[test]
+    fn alarms() {
+        let ics = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+DTSTART:20190102T100000Z\r
+DTEND:20190102T110000Z\r
+RRULE:FREQ=DAILY;COUNT=2\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+TRIGGER:-PT15M\r
+REPEAT:2\r
+DURATION:PT5M\r
+END:VALARM\r
+BEGIN:VALARM\r
+ACTION:AUDIO\r
+TRIGGER;RELATED=END:PT0S\r
+END:VALARM\r
+BEGIN:VALARM\r
+ACTION:EMAIL\r
+TRIGGER;VALUE=DATE-TIME:20190101T120000Z\r
+END:VALARM\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let calendar = Calendar::parse(ics.as_bytes()).unwrap();
+        let times: Vec<Vec<String>> = calendar
+            .iter()
+            .map(|e| {
+                e.alarms
+                    .iter()
+                    .flat_map(|a| a.times(&e))
+                    .map(|t| t.naive().format("%d %H:%M").to_string())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(times, vec![vec!["02 09:45", "02 09:50", "02 09:55", "02 11:00", "01 12:00"],
+                               vec!["03 09:45", "03 09:50", "03 09:55", "03 11:00", "01 12:00"]]);
+        // the absolute trigger is the farthest from the end of the first one
+        assert_eq!(calendar.alarm_reach(), Duration::hours(23));
+    }
+
+    #[test]
+    fn attendees() {
+        let ics = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+DTSTART:20190102T100000Z\r
+ORGANIZER;CN=Alice:mailto:alice@example.com\r
+ATTENDEE;CN=Bob;ROLE=OPT-PARTICIPANT;PARTSTAT=DECLINED:mailto:bob@example.com\r
+ATTENDEE;RSVP=TRUE:MAILTO:carol@example.com\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let event = Calendar::parse(ics.as_bytes()).unwrap().iter().next().unwrap();
+        assert_eq!(event.organizer.as_ref().unwrap().name, "Alice");
+        assert_eq!(event.attendees[0].role, Role::Optional);
+        assert!(event.attendees[1].rsvp);
+        assert_eq!(event.partstat(&["Bob@example.com".to_string()]), Some(PartStat::Declined));
+        assert_eq!(event.partstat(&["carol@example.com".to_string()]), Some(PartStat::NeedsAction));
+        assert_eq!(event.partstat(&["dave@example.com".to_string()]), None);
+    }
+
+    #[test]
+    fn properties() {
+        let ics = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+DTSTART:20190102T100000Z\r
+CATEGORIES:work,meetings\r
+CATEGORIES:a\\,b\r
+CLASS:CONFIDENTIAL\r
+TRANSP:TRANSPARENT\r
+SEQUENCE:3\r
+LAST-MODIFIED:20181231T100000Z\r
+X-GOOGLE-CONFERENCE:https://meet.example.com/abc\r
+X-MICROSOFT-CDO-BUSYSTATUS;X-PARAM=1:FREE\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let event = Calendar::parse(ics.as_bytes()).unwrap().iter().next().unwrap();
+        assert_eq!(event.categories, vec!["work", "meetings", "a,b"]);
+        assert_eq!(event.class, Class::Confidential);
+        assert_eq!(event.transp, Transp::Transparent);
+        assert_eq!(event.sequence, 3);
+        assert!(event.last_modified.is_some());
+        assert_eq!(event.property("X-GOOGLE-CONFERENCE").unwrap().value, "https://meet.example.com/abc");
+        let busy = event.property("X-MICROSOFT-CDO-BUSYSTATUS").unwrap();
+        assert_eq!(busy.params, vec![("X-PARAM".to_string(), vec!["1".to_string()])]);
+    }
+
+    #[test]
+    fn merge() {
+        let work = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+UID:meeting\r
+SEQUENCE:1\r
+DTSTART:20190102T100000Z\r
+SUMMARY:moved meeting\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:lunch\r
+DTSTART:20190102T130000Z\r
+SUMMARY:lunch\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let team = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+UID:meeting\r
+DTSTART:20190101T100000Z\r
+RRULE:FREQ=DAILY;COUNT=3\r
+SUMMARY:meeting\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:lunch\r
+DTSTART:20190102T130000Z\r
+LAST-MODIFIED:20190101T000000Z\r
+SUMMARY:team lunch\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+DTSTART:20190103T130000Z\r
+SUMMARY:no uid\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let mut calendar = Calendar::parse(work.as_bytes()).unwrap();
+        calendar.merge(Calendar::parse(team.as_bytes()).unwrap());
+        let summaries: Vec<String> = calendar.iter().map(|e| e.summary).collect();
+        assert_eq!(summaries, vec!["moved meeting", "team lunch", "no uid"]);
+    }
+
+    #[test]
+    fn merge_instance() {
+        let team = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+UID:meeting\r
+DTSTART:20190101T100000Z\r
+RRULE:FREQ=DAILY;COUNT=3\r
+SUMMARY:meeting\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:meeting\r
+RECURRENCE-ID:20190103T100000Z\r
+DTSTART:20190103T120000Z\r
+SUMMARY:late meeting\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let invite = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+UID:meeting\r
+SEQUENCE:2\r
+RECURRENCE-ID:20190102T100000Z\r
+DTSTART:20190102T090000Z\r
+SUMMARY:early meeting\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let mut calendar = Calendar::parse(team.as_bytes()).unwrap();
+        calendar.merge(Calendar::parse(invite.as_bytes()).unwrap());
+        let events: Vec<String> = calendar.iter().map(|e| e.start.naive().format("%d %H:%M ").to_string() + &e.summary).collect();
+        assert_eq!(events, vec!["01 10:00 meeting", "02 09:00 early meeting", "03 12:00 late meeting"]);
+
+        // the other way around the instance is attached to the series too
+        let mut calendar = Calendar::parse(invite.as_bytes()).unwrap();
+        calendar.merge(Calendar::parse(team.as_bytes()).unwrap());
+        assert_eq!(calendar.iter().count(), 3);
+        let mut written = vec![];
+        calendar.write(&mut written).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert!(written.contains("RECURRENCE-ID:20190102T100000Z\r\n"));
+    }
+
+    #[test]
+    fn recurrence_id() {
+        let ics = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+UID:weekly\r
+RECURRENCE-ID:20190109T100000Z\r
+DTSTART:20190110T120000Z\r
+DTEND:20190110T130000Z\r
+SUMMARY:moved\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:weekly\r
+DTSTART:20190102T100000Z\r
+DTEND:20190102T110000Z\r
+RRULE:FREQ=WEEKLY;COUNT=6\r
+SUMMARY:meeting\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:weekly\r
+RECURRENCE-ID;RANGE=THISANDFUTURE:20190123T100000Z\r
+DTSTART:20190123T090000Z\r
+DTEND:20190123T093000Z\r
+SUMMARY:earlier\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:other\r
+RECURRENCE-ID:20190101T100000Z\r
+DTSTART:20190101T100000Z\r
+DURATION:PT15M\r
+SUMMARY:orphan\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let calendar = Calendar::parse(ics.as_bytes()).unwrap();
+        let events: Vec<String> = calendar
+            .iter()
+            .map(|e| format!("{} {}-{}",
+                             e.summary,
+                             e.start.naive().format("%d %H:%M"),
+                             e.end_date().naive().format("%H:%M")))
+            .collect();
+        assert_eq!(events, vec!["orphan 01 10:00-10:15", "meeting 02 10:00-11:00",
+                                "moved 10 12:00-13:00", "meeting 16 10:00-11:00",
+                                "earlier 23 09:00-09:30", "earlier 30 09:00-09:30",
+                                "earlier 06 09:00-09:30"]);
+    }
+
+    #[test]
+    fn between() {
+        let ics = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+UID:standup\r
+DTSTART;TZID=Europe/Madrid:20000103T093000\r
+DTEND;TZID=Europe/Madrid:20000103T094500\r
+RRULE:FREQ=DAILY\r
+SUMMARY:standup\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:standup\r
+RECURRENCE-ID;TZID=Europe/Madrid:20260330T093000\r
+DTSTART;TZID=Europe/Madrid:20260330T120000\r
+DTEND;TZID=Europe/Madrid:20260330T121500\r
+SUMMARY:moved standup\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:week\r
+DTSTART;VALUE=DATE:20260323\r
+DTEND;VALUE=DATE:20260330\r
+SUMMARY:retreat\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:monthly\r
+DTSTART:20100128T230000Z\r
+DURATION:PT3H\r
+RRULE:FREQ=MONTHLY;UNTIL=20300101T000000Z\r
+SUMMARY:backup\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:pills\r
+DTSTART:20260101T080000Z\r
+RRULE:FREQ=DAILY\r
+SUMMARY:pills\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let calendar = Calendar::parse(ics.as_bytes()).unwrap();
+        let start = Date::parse("20260329T000000", "Europe/Madrid").unwrap();
+        let end = Date::parse("20260331T000000", "Europe/Madrid").unwrap();
+        let events: Vec<Event> = calendar.between(start, end).collect();
+        let stepped: Vec<Event> = calendar
+            .iter()
+            .take_while(|e| e.start < end)
+            .filter(|e| e.overlaps(start, end))
+            .collect();
+        assert_eq!(events, stepped);
+        let summaries: Vec<String> = events
+            .iter()
+            .map(|e| format!("{} {}", e.summary, e.start.naive().format("%d %H:%M")))
+            .collect();
+        // pills has no DTEND
+        assert_eq!(summaries, vec!["retreat 23 00:00", "backup 28 23:00", "standup 29 09:30", "pills 29 08:00",
+                                   "pills 30 08:00", "moved standup 30 12:00"]);
+
+        // the standup never ends, the monthly backup does
+        let all: Vec<Event> = calendar.between(Date::new(), Date::max()).collect();
+        let horizon = Date::now() + Duration::days(367);
+        assert!(all.iter().filter(|e| e.summary == "standup").all(|e| e.start < horizon));
+        assert_eq!(all.iter().filter(|e| e.summary == "backup").count(), 240);
+    }
 }
@@ -1,5 +1,6 @@
 use std::fs::File;
 use std::io::Read;
+use chrono::Weekday;
 use dirs;
 use toml;
 
@@ -11,6 +12,24 @@ const CONFIG_NAME: &str = "almanac.toml";
 pub struct Config {
     pub cals: Vec<String>,
     pub period: String,
+    #[serde(default)]
+    pub remind: String,
+    #[serde(default)]
+    pub emails: Vec<String>,
+    #[serde(default)]
+    pub recursive: bool,
+    #[serde(default = "monday")]
+    pub week_start: Weekday,
+}
+
+fn monday() -> Weekday {
+    Weekday::Mon
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::new()
+    }
 }
 
 impl Config {
@@ -18,6 +37,10 @@ impl Config {
         Config {
             cals: vec![],
             period: "".to_string(),
+            remind: "".to_string(),
+            emails: vec![],
+            recursive: false,
+            week_start: Weekday::Mon,
         }
     }
 
@@ -27,10 +50,11 @@ impl Config {
             None => return Err(ConfigError::MissingPath),
         }.join(CONFIG_NAME);
 
-        let mut file = File::open(&config_path)?;
         let mut toml_str = String::new();
-        file.read_to_string(&mut toml_str)?;
+        File::open(&config_path)
+            .and_then(|mut file| file.read_to_string(&mut toml_str))
+            .map_err(|err| ConfigError::IOError(config_path.clone(), err))?;
 
-        Ok(toml::from_str(&toml_str)?)
+        toml::from_str(&toml_str).map_err(|err| ConfigError::ParseError(config_path, err))
     }
 }
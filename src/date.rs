@@ -1,27 +1,30 @@
 use std::cmp::{Ordering, Ord};
 use std::ops::{Add, Sub};
-use std::slice::Windows;
-use std::str::FromStr;
 
 use errors::EventError;
 
 use chrono;
-use chrono::{TimeZone, Duration, Datelike, Local, Weekday};
-use chrono::offset::Utc;
+use chrono::{TimeZone, Duration, Datelike, Local, NaiveDate, NaiveDateTime, Weekday};
+use chrono::offset::{LocalResult, Utc};
 use chrono_tz::{Tz, UTC};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::Error;
 use windows_timezones::WindowsTimezone;
 
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+// Floating times have no time zone, they are the same wall time in any
+// zone and get interpreted in the local one.
+#[derive(Debug, Copy, Clone)]
 pub enum Date {
     Time(chrono::DateTime<Tz>),
-    AllDay(chrono::Date<Tz>),
+    Floating(NaiveDateTime),
+    AllDay(NaiveDate),
 }
 
 
 impl Date {
     pub fn new() -> Date {
-        Date::Time(UTC.timestamp(0, 0))
+        Date::Time(UTC.timestamp_opt(0, 0).unwrap())
     }
 
 
@@ -30,12 +33,12 @@ impl Date {
     }
 
     pub fn max() -> Date {
-        Date::Time(UTC.timestamp(9_999_999_999, 0))
+        Date::Time(UTC.timestamp_opt(9_999_999_999, 0).unwrap())
     }
 
 
     pub fn parse(date_str: &str, time_zone: &str) -> Result<Self, EventError> {
-        let absolute_time = date_str.chars().rev().next().unwrap() == 'Z';
+        let absolute_time = date_str.ends_with('Z');
         let tz: Tz = if absolute_time {
             UTC
         } else {
@@ -43,46 +46,102 @@ impl Date {
             time_zone.parse().unwrap_or(win_tz)
         };
 
-        let date = match date_str.find("T") {
+        let invalid = || EventError::DateError(date_str.to_string());
+        let date = match date_str.find('T') {
             Some(_) => {
                 let date_pattern = if absolute_time {
                     "%Y%m%dT%H%M%SZ"
                 } else {
                     "%Y%m%dT%H%M%S"
                 };
-                let time = tz.datetime_from_str(&date_str, date_pattern).unwrap_or(
-                    UTC.timestamp(
-                        0,
-                        0,
-                    ),
-                );
-                Date::Time(time)
+                let floating = !absolute_time && time_zone.is_empty();
+                let naive = NaiveDateTime::parse_from_str(date_str, date_pattern).map_err(|_| invalid())?;
+                if floating {
+                    Date::Floating(naive)
+                } else {
+                    Date::Time(from_local(&tz, &naive))
+                }
             }
             None => {
-                Date::AllDay(tz.ymd(
-                    date_str[0..4].parse()?,
-                    date_str[4..6].parse()?,
-                    date_str[6..8].parse()?,
-                ))
+                let date = NaiveDate::parse_from_str(date_str, "%Y%m%d").map_err(|_| invalid())?;
+                Date::AllDay(date)
             }
         };
         Ok(date)
     }
 
+    pub fn naive(&self) -> NaiveDateTime {
+        match *self {
+            Date::Time(t) => t.naive_local(),
+            Date::Floating(t) => t,
+            Date::AllDay(d) => d.and_hms_opt(0, 0, 0).unwrap(),
+        }
+    }
+
+    // The wall time in the local time zone, floating and all day dates are
+    // already in it
+    pub fn local(&self) -> NaiveDateTime {
+        match *self {
+            Date::Time(t) => t.with_timezone(&Local).naive_local(),
+            _ => self.naive(),
+        }
+    }
+
+    pub fn with_naive(&self, naive: NaiveDateTime) -> Date {
+        match *self {
+            Date::Time(t) => Date::Time(from_local(&t.timezone(), &naive)),
+            Date::Floating(_) => Date::Floating(naive),
+            Date::AllDay(_) => Date::AllDay(naive.date()),
+        }
+    }
+
+    // Dates with time as an instant, floating ones in the local time zone
+    fn instant(&self) -> Option<chrono::DateTime<Tz>> {
+        match *self {
+            Date::Time(t) => Some(t),
+            Date::Floating(t) => Some(from_local(&Local, &t).with_timezone(&UTC)),
+            Date::AllDay(_) => None,
+        }
+    }
+
+    // Floating times and all day dates are taken in the local time zone
+    pub fn utc(&self) -> NaiveDateTime {
+        match self.instant() {
+            Some(t) => t.naive_utc(),
+            None => from_local(&Local, &self.naive()).naive_utc(),
+        }
+    }
+
+    fn date_naive(&self) -> NaiveDate {
+        match *self {
+            Date::Time(t) => t.date_naive(),
+            Date::Floating(t) => t.date(),
+            Date::AllDay(d) => d,
+        }
+    }
+
     pub fn format(&self, fmt: &str) -> String {
         match *self {
             Date::Time(t) => t.with_timezone(&Local).format(fmt).to_string(),
+            Date::Floating(t) => t.format(fmt).to_string(),
             Date::AllDay(d) => d.format(fmt).to_string(),
         }
     }
 
+    // Days in the zone of each date, the same ones Ord compares
     pub fn same_day(&self, other: &Date) -> bool {
-        self.day() == other.day() && self.month() == other.month() && self.year() == self.year()
+        self.date_naive() == other.date_naive()
+    }
+
+    // Days as they are seen in the local time zone
+    pub fn same_local_day(&self, other: &Date) -> bool {
+        self.local().date() == other.local().date()
     }
 
     pub fn day(&self) -> u32 {
         match *self {
             Date::Time(t) => t.day(),
+            Date::Floating(t) => t.day(),
             Date::AllDay(d) => d.day(),
         }
     }
@@ -90,6 +149,7 @@ impl Date {
     pub fn with_day(&self, day: u32) -> Option<Date> {
         Some(match *self {
             Date::Time(t) => Date::Time(t.with_day(day)?),
+            Date::Floating(t) => Date::Floating(t.with_day(day)?),
             Date::AllDay(d) => Date::AllDay(d.with_day(day)?),
         })
     }
@@ -97,6 +157,7 @@ impl Date {
     pub fn weekday(&self) -> Weekday {
         match *self {
             Date::Time(t) => t.weekday(),
+            Date::Floating(t) => t.weekday(),
             Date::AllDay(d) => d.weekday(),
         }
     }
@@ -104,6 +165,7 @@ impl Date {
     pub fn month(&self) -> u32 {
         match *self {
             Date::Time(t) => t.month(),
+            Date::Floating(t) => t.month(),
             Date::AllDay(d) => d.month(),
         }
     }
@@ -111,6 +173,7 @@ impl Date {
     pub fn with_month(&self, month: u32) -> Option<Date> {
         Some(match *self {
             Date::Time(t) => Date::Time(t.with_month(month)?),
+            Date::Floating(t) => Date::Floating(t.with_month(month)?),
             Date::AllDay(d) => Date::AllDay(d.with_month(month)?),
         })
     }
@@ -124,6 +187,7 @@ impl Date {
     pub fn year(&self) -> i32 {
         match *self {
             Date::Time(t) => t.year(),
+            Date::Floating(t) => t.year(),
             Date::AllDay(d) => d.year(),
         }
     }
@@ -131,37 +195,69 @@ impl Date {
     pub fn with_year(&self, year: i32) -> Option<Date> {
         Some(match *self {
             Date::Time(t) => Date::Time(t.with_year(year)?),
+            Date::Floating(t) => Date::Floating(t.with_year(year)?),
             Date::AllDay(d) => Date::AllDay(d.with_year(year)?),
         })
     }
 
     pub fn days_in_month(&self) -> u32 {
-        chrono::NaiveDate::from_ymd_opt(self.year(), self.month() + 1, 1)
-            .unwrap_or(chrono::NaiveDate::from_ymd(self.year() + 1, 1, 1))
-            .pred()
-            .day()
+        days_in_month(self.year(), self.month())
+    }
+}
+
+impl Default for Date {
+    fn default() -> Self {
+        Date::new()
+    }
+}
+
+pub fn days_in_month(year: i32, month: u32) -> u32 {
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    NaiveDate::from_ymd_opt(next_year, next_month, 1)
+        .and_then(|d| d.pred_opt())
+        .map(|d| d.day())
+        .unwrap_or(31)
+}
+
+// Local times that fall in a DST gap are shifted forward using the offset
+// before the gap, as RFC 5545 mandates.
+fn from_local<T: TimeZone>(tz: &T, naive: &NaiveDateTime) -> chrono::DateTime<T> {
+    match tz.from_local_datetime(naive) {
+        LocalResult::Single(t) => t,
+        LocalResult::Ambiguous(t, _) => t,
+        LocalResult::None => {
+            match tz.from_local_datetime(&(*naive - Duration::hours(1))) {
+                LocalResult::Single(t) |
+                LocalResult::Ambiguous(t, _) => t + Duration::hours(1),
+                LocalResult::None => tz.from_utc_datetime(naive),
+            }
+        }
     }
 }
 
 impl Ord for Date {
     fn cmp(&self, other: &Self) -> Ordering {
-        match *self {
-            Date::Time(t1) => {
-                match *other {
-                    Date::Time(t2) => t1.cmp(&t2),
-                    Date::AllDay(d) => cmp_date_time(&d, &t1).reverse(),
-                }
-            }
-            Date::AllDay(d1) => {
-                match *other {
-                    Date::Time(t) => cmp_date_time(&d1, &t),
-                    Date::AllDay(d2) => d1.cmp(&d2),
-                }
-            }
+        match (self.instant(), other.instant()) {
+            (Some(t1), Some(t2)) => t1.cmp(&t2),
+            (Some(_), None) => cmp_date_time(&other.date_naive(), &self.date_naive()).reverse(),
+            (None, Some(_)) => cmp_date_time(&self.date_naive(), &other.date_naive()),
+            (None, None) => self.date_naive().cmp(&other.date_naive()),
         }
     }
 }
 
+impl PartialEq for Date {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Date {}
+
 impl PartialOrd for Date {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -174,6 +270,7 @@ impl Add<Duration> for Date {
     fn add(self, other: Duration) -> Date {
         match self {
             Date::Time(d) => Date::Time(d + other),
+            Date::Floating(d) => Date::Floating(d + other),
             Date::AllDay(d) => Date::AllDay(d + other),
         }
     }
@@ -183,49 +280,118 @@ impl Sub<Date> for Date {
     type Output = Duration;
 
     fn sub(self, other: Self) -> Duration {
-        match self {
-            Date::Time(t1) => {
-                match other {
-                    Date::Time(t2) => t1 - t2,
-                    Date::AllDay(d) => t1.date() - d,
-                }
-            }
-            Date::AllDay(d1) => {
-                match other {
-                    Date::Time(t) => d1 - t.date(),
-                    Date::AllDay(d2) => d1 - d2,
-                }
-            }
+        match (self, other) {
+            (Date::Floating(t1), Date::Floating(t2)) => t1 - t2,
+            _ => match (self.instant(), other.instant()) {
+                (Some(t1), Some(t2)) => t1 - t2,
+                _ => self.date_naive() - other.date_naive(),
+            },
         }
     }
 }
 
-fn cmp_date_time<T: TimeZone>(date: &chrono::Date<T>, time: &chrono::DateTime<T>) -> Ordering {
-    let d2 = time.date();
-    if date.eq(&d2) {
+// Times are stored as a UTC timestamp and the name of their zone
+#[derive(Serialize, Deserialize)]
+enum DateRepr {
+    Time(i64, u32, String),
+    Floating(NaiveDateTime),
+    AllDay(NaiveDate),
+}
+
+impl Serialize for Date {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let repr = match *self {
+            Date::Time(t) => DateRepr::Time(t.timestamp(), t.timestamp_subsec_nanos(), t.timezone().name().to_string()),
+            Date::Floating(t) => DateRepr::Floating(t),
+            Date::AllDay(d) => DateRepr::AllDay(d),
+        };
+        repr.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Date {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match DateRepr::deserialize(deserializer)? {
+            DateRepr::Time(secs, nanos, zone) => {
+                let tz: Tz = zone.parse().map_err(D::Error::custom)?;
+                let time = tz.timestamp_opt(secs, nanos).single();
+                Date::Time(time.ok_or_else(|| D::Error::custom("invalid timestamp"))?)
+            }
+            DateRepr::Floating(t) => Date::Floating(t),
+            DateRepr::AllDay(d) => Date::AllDay(d),
+        })
+    }
+}
+
+// Durations as milliseconds, for #[serde(with = "::date::duration")]
+pub mod duration {
+    use chrono::Duration;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(duration.num_milliseconds())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        Ok(Duration::milliseconds(i64::deserialize(deserializer)?))
+    }
+}
+
+// An all day date goes before the times of the same day
+fn cmp_date_time(date: &NaiveDate, time_date: &NaiveDate) -> Ordering {
+    if date.eq(time_date) {
         return Ordering::Less;
     }
-    date.cmp(&d2)
+    date.cmp(time_date)
 }
 
 #[cfg(test)]
 mod tests {
     use super::Date;
+    use chrono::{Local, NaiveDate, TimeZone};
+    use chrono::Duration;
     use chrono::Datelike;
     use chrono::Timelike;
+    use chrono_tz::{Tz, UTC};
 
     #[test]
     fn date_parse_time() {
-        match Date::parse("19361020T120000", "").unwrap() {
+        match Date::parse("19361020T120000Z", "").unwrap() {
             Date::Time(time) => {
                 assert_eq!(time.year(), 1936);
                 assert_eq!(time.hour(), 12);
                 assert_eq!(time.day(), 20);
             }
-            _ => assert!(true),
+            _ => panic!("expected a date with time"),
         }
     }
 
+    #[test]
+    fn date_parse_floating() {
+        match Date::parse("19361020T120000", "").unwrap() {
+            Date::Floating(time) => {
+                assert_eq!(time.year(), 1936);
+                assert_eq!(time.hour(), 12);
+                assert_eq!(time.day(), 20);
+            }
+            _ => panic!("expected a floating date"),
+        }
+        let date = Date::parse("19361020T090000", "").unwrap();
+        assert_eq!(date.format("%H:%M"), "09:00");
+        assert_eq!((date + Duration::hours(1)).format("%H:%M"), "10:00");
+    }
+
+    #[test]
+    fn date_ord_floating() {
+        let floating = Date::parse("19361020T120000", "").unwrap();
+        let day = Date::parse("19361020", "").unwrap();
+        let later = Date::parse("19361020T130000", "").unwrap();
+        assert!(day < floating);
+        assert!(floating < later);
+        assert_eq!(later - floating, Duration::hours(1));
+        assert_eq!(floating, Date::parse("19361020T120000", "").unwrap());
+    }
+
     #[test]
     fn date_parse_allday() {
         match Date::parse("19361020", "").unwrap() {
@@ -234,7 +400,7 @@ mod tests {
                 assert_eq!(time.month(), 10);
                 assert_eq!(time.day(), 20);
             }
-            _ => assert!(true),
+            _ => panic!("expected an all day date"),
         }
     }
 
@@ -244,20 +410,52 @@ mod tests {
         let d2 = Date::parse("19361022", "").unwrap();
         let t1 = Date::parse("19361020T120000", "").unwrap();
         let t2 = Date::parse("19361018T120000", "").unwrap();
-        if d1 > d2 {
-            assert!(true)
-        }
-        if d1 != d1 {
-            assert!(true)
-        }
-        if t2 > d1 {
-            assert!(true)
-        }
-        if t1 < t2 {
-            assert!(true)
-        }
-        if t1 > d2 {
-            assert!(true)
-        }
+        assert!(d1 < d2);
+        assert!(t2 < d1);
+        assert!(t1 > t2);
+        assert!(t1 > d1);
+        assert!(t1 < d2);
+    }
+
+    #[test]
+    fn date_parse_invalid() {
+        assert!(Date::parse("1936", "").is_err());
+        assert!(Date::parse("19361320", "").is_err());
+        assert!(Date::parse("19361020T1200", "Europe/Madrid").is_err());
+    }
+
+    #[test]
+    fn date_dst_gap() {
+        let date = Date::parse("20190331T023000", "Europe/Madrid").unwrap();
+        assert_eq!(date.naive().hour(), 3);
+        assert_eq!(date.naive().minute(), 30);
+    }
+
+    #[test]
+    fn local_day_near_midnight() {
+        // the same local day seen from a zone 14 hours ahead of UTC
+        let kiritimati: Tz = "Etc/GMT-14".parse().unwrap();
+        let day = NaiveDate::from_ymd_opt(2026, 10, 22).unwrap();
+        let at = |h, m| {
+            let local = Local.from_local_datetime(&day.and_hms_opt(h, m, 0).unwrap()).unwrap();
+            Date::Time(local.with_timezone(&kiritimati))
+        };
+        assert!(at(0, 30).same_local_day(&at(10, 0)));
+        assert!(at(23, 59).same_local_day(&Date::AllDay(day)));
+        assert!(!at(0, 30).same_local_day(&Date::AllDay(day.pred_opt().unwrap())));
+    }
+
+    #[test]
+    fn same_day_in_own_zone() {
+        // 23:30 UTC is already the next day in Kiritimati
+        let kiritimati: Tz = "Etc/GMT-14".parse().unwrap();
+        let day = NaiveDate::from_ymd_opt(2026, 10, 22).unwrap();
+        let utc = Date::Time(UTC.from_utc_datetime(&day.and_hms_opt(23, 30, 0).unwrap()));
+        let there = Date::Time(kiritimati.from_utc_datetime(&day.and_hms_opt(23, 30, 0).unwrap()));
+        assert!(utc.same_day(&Date::AllDay(day)));
+        assert!(there.same_day(&Date::AllDay(day.succ_opt().unwrap())));
+        // like Ord, that puts the day before its times
+        assert!(Date::AllDay(day.succ_opt().unwrap()) < there);
+        assert!(Date::AllDay(day) < utc);
     }
 }
@@ -0,0 +1,82 @@
+use std::io;
+use std::io::{BufRead, BufReader, Cursor, Read};
+
+use flate2::bufread::MultiGzDecoder;
+use lzma_rs;
+use ruzstd::StreamingDecoder;
+
+const GZIP: &[u8] = &[0x1f, 0x8b];
+const ZSTD: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const XZ: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+// Looks at the first bytes of the input and decompresses it if it is gzip,
+// zstd or xz. Anything else is passed through untouched.
+pub fn decompress<'a, R: BufRead + 'a>(mut input: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    let mut magic = vec![];
+    input.by_ref().take(XZ.len() as u64).read_to_end(&mut magic)?;
+    let mut input = Cursor::new(magic.clone()).chain(input);
+
+    if magic.starts_with(GZIP) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(input))))
+    } else if magic.starts_with(ZSTD) {
+        let decoder = StreamingDecoder::new(input).map_err(io::Error::other)?;
+        Ok(Box::new(BufReader::new(decoder)))
+    } else if magic.starts_with(XZ) {
+        // lzma-rs only decompresses into a writer
+        let mut decompressed = vec![];
+        lzma_rs::xz_decompress(&mut input, &mut decompressed).map_err(io::Error::other)?;
+        Ok(Box::new(Cursor::new(decompressed)))
+    } else {
+        Ok(Box::new(input))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use lzma_rs;
+
+    use super::decompress;
+
+    const ICS: &str = "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n";
+
+    fn read(input: &[u8]) -> String {
+        let mut text = String::new();
+        decompress(input).unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn plain() {
+        assert_eq!(read(ICS.as_bytes()), ICS);
+        assert_eq!(read(b"BEG"), "BEG");
+    }
+
+    #[test]
+    fn gzip() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(ICS.as_bytes()).unwrap();
+        assert_eq!(read(&encoder.finish().unwrap()), ICS);
+    }
+
+    #[test]
+    fn xz() {
+        let mut compressed = vec![];
+        lzma_rs::xz_compress(&mut ICS.as_bytes(), &mut compressed).unwrap();
+        assert_eq!(read(&compressed), ICS);
+    }
+
+    #[test]
+    fn zstd() {
+        // zstd -c of ICS
+        let compressed = [
+            0x28, 0xb5, 0x2f, 0xfd, 0x04, 0x58, 0xd5, 0x00, 0x00, 0xa0, 0x42, 0x45, 0x47, 0x49, 0x4e, 0x3a,
+            0x56, 0x43, 0x41, 0x4c, 0x45, 0x4e, 0x44, 0x41, 0x52, 0x0d, 0x0a, 0x45, 0x4e, 0x44, 0x01, 0x00,
+            0x84, 0x9b, 0x4c, 0xb4, 0xba, 0xde, 0x61,
+        ];
+        assert_eq!(read(&compressed), ICS);
+    }
+}
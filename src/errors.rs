@@ -1,15 +1,96 @@
+use std::error::Error;
+use std::fmt;
 use std::io;
 use std::num::ParseIntError;
+use std::path::PathBuf;
 use ical::parser::ParserError;
 use toml;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum EventError {
     IcalError(ParserError),
     IntError(ParseIntError),
-    StatusError,
-    FreqError,
-    BydayError,
+    IOError(io::Error),
+    StatusError(String),
+    FreqError(String),
+    BydayError(String),
+    RuleError(String),
+    DateError(String),
+    DurationError(String),
+    FetchError(String),
+    RangeError(String),
+    // The property of a component that failed to parse
+    PropertyError {
+        property: String,
+        value: String,
+        uid: String,
+        cause: Box<EventError>,
+    },
+    FileError {
+        path: String,
+        cause: Box<EventError>,
+    },
+}
+
+impl EventError {
+    pub fn in_property(self, property: &str, value: &str, uid: &str) -> EventError {
+        EventError::PropertyError {
+            property: property.to_string(),
+            value: value.to_string(),
+            uid: uid.to_string(),
+            cause: Box::new(self),
+        }
+    }
+
+    pub fn in_file(self, path: &str) -> EventError {
+        EventError::FileError {
+            path: path.to_string(),
+            cause: Box::new(self),
+        }
+    }
+}
+
+impl fmt::Display for EventError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EventError::IcalError(ref err) => write!(f, "malformed calendar: {}", err),
+            EventError::IntError(ref err) => write!(f, "invalid number: {}", err),
+            EventError::IOError(ref err) => write!(f, "{}", err),
+            EventError::StatusError(ref s) => write!(f, "invalid status {:?}", s),
+            EventError::FreqError(ref s) => write!(f, "invalid frequency {:?}", s),
+            EventError::BydayError(ref s) => write!(f, "invalid weekday {:?}", s),
+            EventError::RuleError(ref s) => write!(f, "out of range rule part {:?}", s),
+            EventError::DateError(ref s) => write!(f, "invalid date {:?}", s),
+            EventError::DurationError(ref s) => write!(f, "invalid duration {:?}", s),
+            EventError::FetchError(ref s) => write!(f, "{}", s),
+            EventError::RangeError(ref s) => write!(f, "invalid time frame {:?}", s),
+            EventError::PropertyError { ref property, ref value, ref uid, ref cause } => {
+                write!(f, "{}", property)?;
+                if !value.is_empty() {
+                    write!(f, " {:?}", value)?;
+                }
+                if !uid.is_empty() {
+                    write!(f, " in {}", uid)?;
+                }
+                write!(f, ": {}", cause)
+            }
+            EventError::FileError { ref path, ref cause } => write!(f, "{}: {}", path, cause),
+        }
+    }
+}
+
+impl Error for EventError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            EventError::IcalError(ref err) => Some(err),
+            EventError::IntError(ref err) => Some(err),
+            EventError::IOError(ref err) => Some(err),
+            EventError::PropertyError { ref cause, .. } |
+            EventError::FileError { ref cause, .. } => Some(cause.as_ref()),
+            _ => None,
+        }
+    }
 }
 
 impl From<ParserError> for EventError {
@@ -24,21 +105,35 @@ impl From<ParseIntError> for EventError {
     }
 }
 
+impl From<io::Error> for EventError {
+    fn from(err: io::Error) -> EventError {
+        EventError::IOError(err)
+    }
+}
+
 #[derive(Debug)]
 pub enum ConfigError {
-    IOError(io::Error),
-    ParseError(toml::de::Error),
+    IOError(PathBuf, io::Error),
+    ParseError(PathBuf, toml::de::Error),
     MissingPath,
 }
 
-impl From<io::Error> for ConfigError {
-    fn from(err: io::Error) -> ConfigError {
-        ConfigError::IOError(err)
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::IOError(ref path, ref err) => write!(f, "{}: {}", path.display(), err),
+            ConfigError::ParseError(ref path, ref err) => write!(f, "{}: {}", path.display(), err),
+            ConfigError::MissingPath => write!(f, "no configuration directory found"),
+        }
     }
 }
 
-impl From<toml::de::Error> for ConfigError {
-    fn from(err: toml::de::Error) -> ConfigError {
-        ConfigError::ParseError(err)
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ConfigError::IOError(_, ref err) => Some(err),
+            ConfigError::ParseError(_, ref err) => Some(err),
+            ConfigError::MissingPath => None,
+        }
     }
 }
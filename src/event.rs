@@ -4,44 +4,102 @@ use std::str::FromStr;
 
 use chrono::Duration;
 
+use alarm::Alarm;
+use attendee::{Attendee, Organizer, PartStat};
 use date::Date;
 use errors::EventError;
+use writer::Params;
 
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Event {
+    pub uid: String,
     pub start: Date,
     pub end: End,
     pub summary: String,
     pub location: String,
     pub description: String,
-    pub status: Status,
+    // None when the event has no STATUS, it is taken as confirmed
+    pub status: Option<Status>,
+    pub alarms: Vec<Alarm>,
+    pub organizer: Option<Organizer>,
+    pub attendees: Vec<Attendee>,
+    pub categories: Vec<String>,
+    pub url: String,
+    pub class: Class,
+    pub transp: Transp,
+    pub priority: u32,
+    pub sequence: u32,
+    pub created: Option<Date>,
+    pub last_modified: Option<Date>,
+    // Parameters of SUMMARY, LOCATION and DESCRIPTION, like ALTREP or
+    // LANGUAGE, by property name
+    pub text_params: Vec<(String, Params)>,
+    // Everything else, X- extensions included, as found in the file
+    pub properties: Vec<Property>,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Property {
+    pub name: String,
+    pub params: Vec<(String, Vec<String>)>,
+    pub value: String,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Serialize, Deserialize)]
 pub enum Status {
     Confirmed,
     Tentative,
     Canceled,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq)]
+// Unknown classes are kept to write them back, they should be taken as
+// private
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Class {
+    Public,
+    Private,
+    Confidential,
+    Other(String),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Transp {
+    Opaque,
+    Transparent,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Serialize, Deserialize)]
 pub enum End {
     Date(Date),
-    Duration(Duration),
+    Duration(#[serde(with = "::date::duration")] Duration),
 }
 
 
 impl Event {
     pub fn new() -> Event {
-        return Event {
+        Event {
+            uid: "".to_string(),
             summary: "".to_string(),
             location: "".to_string(),
             description: "".to_string(),
-            status: Status::Confirmed,
+            status: None,
             start: Date::new(),
             end: End::Date(Date::new()),
-        };
+            alarms: vec![],
+            organizer: None,
+            attendees: vec![],
+            categories: vec![],
+            url: "".to_string(),
+            class: Class::Public,
+            transp: Transp::Opaque,
+            priority: 0,
+            sequence: 0,
+            created: None,
+            last_modified: None,
+            text_params: vec![],
+            properties: vec![],
+        }
     }
 
     pub fn end_date(&self) -> Date {
@@ -50,6 +108,29 @@ impl Event {
             End::Duration(duration) => self.start + duration,
         }
     }
+
+    // Events without duration overlap the window if they are in it
+    pub fn overlaps(&self, start: Date, end: Date) -> bool {
+        self.start < end && (self.end_date() > start || self.start >= start)
+    }
+
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.iter().find(|p| p.name == name)
+    }
+
+    // Participation of the first attendee with any of the emails
+    pub fn partstat(&self, emails: &[String]) -> Option<PartStat> {
+        self.attendees
+            .iter()
+            .find(|a| emails.iter().any(|email| a.email().eq_ignore_ascii_case(email)))
+            .map(|a| a.partstat)
+    }
+}
+
+impl Default for Event {
+    fn default() -> Self {
+        Event::new()
+    }
 }
 
 impl fmt::Display for Event {
@@ -88,6 +169,16 @@ impl PartialOrd for Event {
     }
 }
 
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Status::Confirmed => "CONFIRMED",
+            Status::Tentative => "TENTATIVE",
+            Status::Canceled => "CANCELLED",
+        })
+    }
+}
+
 impl FromStr for Status {
     type Err = EventError;
 
@@ -96,7 +187,47 @@ impl FromStr for Status {
             "CONFIRMED" => Ok(Status::Confirmed),
             "TENTATIVE" => Ok(Status::Tentative),
             "CANCELLED" => Ok(Status::Canceled),
-            _ => Err(EventError::StatusError),
+            _ => Err(EventError::StatusError(s.to_string())),
+        }
+    }
+}
+
+impl From<&str> for Class {
+    fn from(s: &str) -> Class {
+        match s {
+            "PUBLIC" => Class::Public,
+            "PRIVATE" => Class::Private,
+            "CONFIDENTIAL" => Class::Confidential,
+            _ => Class::Other(s.to_string()),
         }
     }
 }
+
+impl From<&str> for Transp {
+    fn from(s: &str) -> Transp {
+        match s {
+            "TRANSPARENT" => Transp::Transparent,
+            _ => Transp::Opaque,
+        }
+    }
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Class::Public => "PUBLIC",
+            Class::Private => "PRIVATE",
+            Class::Confidential => "CONFIDENTIAL",
+            Class::Other(ref class) => class,
+        })
+    }
+}
+
+impl fmt::Display for Transp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Transp::Opaque => "OPAQUE",
+            Transp::Transparent => "TRANSPARENT",
+        })
+    }
+}
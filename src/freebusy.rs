@@ -0,0 +1,89 @@
+use std::cmp::{Ordering, Ord};
+use std::fmt;
+
+use date::Date;
+
+
+// A VFREEBUSY component, with the time it covers and its busy periods
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FreeBusy {
+    pub uid: String,
+    pub organizer: String,
+    pub start: Option<Date>,
+    pub end: Option<Date>,
+    pub periods: Vec<Busy>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Busy {
+    pub start: Date,
+    pub end: Date,
+    pub kind: BusyType,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Serialize, Deserialize)]
+pub enum BusyType {
+    Free,
+    Busy,
+    Unavailable,
+    Tentative,
+}
+
+impl FreeBusy {
+    pub fn new() -> FreeBusy {
+        FreeBusy {
+            uid: "".to_string(),
+            organizer: "".to_string(),
+            start: None,
+            end: None,
+            periods: vec![],
+        }
+    }
+}
+
+impl Default for FreeBusy {
+    fn default() -> Self {
+        FreeBusy::new()
+    }
+}
+
+impl fmt::Display for Busy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}-{:?}: {:?}", self.start, self.end, self.kind)
+    }
+}
+
+impl Ord for Busy {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.start.cmp(&other.start).then_with(|| self.end.cmp(&other.end))
+    }
+}
+
+impl PartialOrd for Busy {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BusyType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            BusyType::Free => "FREE",
+            BusyType::Busy => "BUSY",
+            BusyType::Unavailable => "BUSY-UNAVAILABLE",
+            BusyType::Tentative => "BUSY-TENTATIVE",
+        })
+    }
+}
+
+// Unknown types are taken as busy, RFC 5545 3.2.9
+impl From<&str> for BusyType {
+    fn from(s: &str) -> BusyType {
+        match s {
+            "FREE" => BusyType::Free,
+            "BUSY-UNAVAILABLE" => BusyType::Unavailable,
+            "BUSY-TENTATIVE" => BusyType::Tentative,
+            _ => BusyType::Busy,
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::cmp::{Ordering, Ord};
+use std::fmt;
+
+use date::Date;
+use event::Property;
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Journal {
+    pub uid: String,
+    // journals without DTSTART are not placed in time
+    pub start: Option<Date>,
+    pub summary: String,
+    pub description: String,
+    pub properties: Vec<Property>,
+}
+
+impl Journal {
+    pub fn new() -> Journal {
+        Journal {
+            uid: "".to_string(),
+            start: None,
+            summary: "".to_string(),
+            description: "".to_string(),
+            properties: vec![],
+        }
+    }
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Journal::new()
+    }
+}
+
+impl fmt::Display for Journal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.start {
+            Some(start) => write!(f, "{:?}: {}", start, self.summary)?,
+            None => write!(f, "-: {}", self.summary)?,
+        }
+        if !self.description.is_empty() {
+            write!(f, "\n\t{}", self.description)?;
+        }
+        Ok(())
+    }
+}
+
+// The undated ones go last
+impl Ord for Journal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let start = match (self.start, other.start) {
+            (Some(d1), Some(d2)) => d1.cmp(&d2),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        start.then_with(|| self.summary.cmp(&other.summary))
+    }
+}
+
+impl PartialOrd for Journal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
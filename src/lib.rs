@@ -4,6 +4,17 @@ extern crate chrono_tz;
 extern crate itertools;
 extern crate dirs;
 extern crate toml;
+extern crate ureq;
+extern crate serde;
+extern crate bincode;
+extern crate url;
+extern crate percent_encoding;
+extern crate base64;
+extern crate roxmltree;
+extern crate rayon;
+extern crate flate2;
+extern crate ruzstd;
+extern crate lzma_rs;
 
 #[macro_use]
 extern crate serde_derive;
@@ -11,13 +22,40 @@ extern crate windows_timezones;
 
 mod date;
 mod event;
+mod alarm;
+mod attendee;
+mod todo;
+mod journal;
+mod freebusy;
+mod rrule;
 mod periodic;
+mod timezone;
 mod calendar;
 mod config;
 mod errors;
+mod writer;
+mod fetch;
+mod cache;
+mod caldav;
+mod vdir;
+mod decompress;
+mod range;
 
 pub use calendar::Calendar;
 pub use date::Date;
-pub use chrono::Duration;
-pub use event::Event;
+pub use chrono::{Duration, Weekday};
+pub use event::{Event, End, Status, Class, Transp, Property};
+pub use alarm::{Alarm, Action, Trigger};
+pub use attendee::{Attendee, Organizer, Role, PartStat};
+pub use todo::{Todo, TodoStatus};
+pub use journal::Journal;
+pub use freebusy::{FreeBusy, Busy, BusyType};
 pub use config::Config;
+pub use timezone::Timezone;
+pub use errors::{EventError, ConfigError};
+pub use writer::Writer;
+pub use fetch::{fetch, is_url, without_userinfo, Feed, Fetched};
+pub use cache::{Cache, file_stamp, feed_stamp};
+pub use caldav::{CalDav, is_caldav};
+pub use decompress::decompress;
+pub use range::parse_range;
@@ -1,71 +1,310 @@
 extern crate almanac;
+extern crate chrono;
 extern crate itertools;
 extern crate colored;
+extern crate terminal_size;
 
+use std::cmp;
+use std::collections::HashSet;
 use std::env;
-use std::io::BufReader;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::fs;
 use std::fs::File;
+use std::path::Path;
+use std::process;
+use std::process::Command;
+use std::thread;
+use std::time;
+use chrono::{Datelike, Months, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
 use itertools::Itertools;
 use colored::*;
+use terminal_size::{terminal_size, Width};
 
 use almanac::Calendar;
 use almanac::Date;
 use almanac::Duration;
+use almanac::Weekday;
 use almanac::Event;
+use almanac::PartStat;
+use almanac::Status;
+use almanac::Todo;
 use almanac::Config;
+use almanac::ConfigError;
+use almanac::EventError;
+use almanac::{fetch, is_url, without_userinfo, Fetched};
+use almanac::{Cache, file_stamp, feed_stamp};
+use almanac::{CalDav, is_caldav};
+use almanac::decompress;
+use almanac::parse_range;
+
+const FLAGS: &[&str] = &["--strict", "--no-merge", "--no-cache", "--recursive", "--grid", "--three", "--agenda"];
+
+const USAGE: &str = "Usage: almanac [--strict] [--no-merge] [--no-cache] [--recursive] [--grid] [--three] [--agenda] \
+                     TIMEFRAME|cal|agenda|todo|remind|export [ical ...]\n       \
+                     almanac [options] [--from TIMEFRAME] [--to TIMEFRAME] [ical ...]";
 
 fn main() {
-    let conf = Config::parse().unwrap_or(Config::new());
-    let mut args = env::args().skip(1);
-    let period_arg = match args.next() {
-        Some(arg) => arg,
-        None => {
-            if conf.period.is_empty() {
-                println!("Usage: almanac day|week|month [ical ...]");
-                return;
+    let conf = match Config::parse() {
+        Ok(conf) => conf,
+        Err(ConfigError::IOError(_, ref err)) if err.kind() == io::ErrorKind::NotFound => Config::default(),
+        Err(ConfigError::MissingPath) => Config::default(),
+        Err(err) => fail(&err),
+    };
+    let mut flags = vec![];
+    let mut args = vec![];
+    let mut from = None;
+    let mut to = None;
+    let mut argv = env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        let (name, value) = match arg.find('=') {
+            Some(pos) if arg.starts_with("--") => (arg[..pos].to_string(), Some(arg[pos + 1..].to_string())),
+            _ => (arg.clone(), None),
+        };
+        if name == "--from" || name == "--to" {
+            let value = match value.or_else(|| argv.next()) {
+                Some(value) => value,
+                None => fail(&format!("{} needs a time frame", name)),
+            };
+            if name == "--from" {
+                from = Some(value);
             } else {
-                conf.period
+                to = Some(value);
+            }
+        } else if FLAGS.contains(&arg.as_str()) {
+            flags.push(arg);
+        } else if arg.starts_with("--") {
+            eprintln!("almanac: unknown option {}\n{}", arg, USAGE);
+            process::exit(1);
+        } else {
+            args.push(arg);
+        }
+    }
+    let strict = flags.iter().any(|flag| flag == "--strict");
+    let merge = !flags.iter().any(|flag| flag == "--no-merge");
+    let recursive = conf.recursive || flags.iter().any(|flag| flag == "--recursive");
+    let cache = if flags.iter().any(|flag| flag == "--no-cache") {
+        None
+    } else {
+        Cache::new()
+    };
+    let mut args = args.into_iter();
+    // with --from or --to all the arguments are calendars
+    let period_arg = if from.is_some() || to.is_some() {
+        "".to_string()
+    } else {
+        match args.next() {
+            Some(arg) => arg,
+            None => {
+                if conf.period.is_empty() {
+                    println!("{}", USAGE);
+                    return;
+                } else {
+                    conf.period
+                }
             }
         }
     };
-    let (first, last) = period(&period_arg);
+    let mut paths: Vec<String> = args.collect();
+    if paths.is_empty() {
+        paths = conf.cals;
+    }
+    // None for the modes that are not about a time frame
+    let range = match period(&period_arg, from, to, conf.week_start) {
+        Ok(range) => range,
+        Err(err) => fail(&err),
+    };
+    let agenda = period_arg == "agenda" || flags.iter().any(|flag| flag == "--agenda");
+    let grid = period_arg == "cal" || flags.iter().any(|flag| flag == "--grid");
+    let three = flags.iter().any(|flag| flag == "--three");
+    let week_start = conf.week_start;
+    // the week table and the grid show more than the time frame, the CalDAV
+    // servers are asked for all of it
+    let shown = range.map(|(first, last)| {
+        if agenda {
+            week_window(first, week_start)
+        } else if grid {
+            grid_window(first, three)
+        } else {
+            (first, last)
+        }
+    });
+    let calendars = match load(&paths, strict, recursive, merge, cache.as_ref(), shown) {
+        Ok(calendars) => calendars,
+        Err(err) => fail(&err),
+    };
 
-    let mut calendars: Vec<_> = args.map(|arg| ics_calendar(&arg)).collect();
-    if calendars.is_empty() {
-        for cal in &conf.cals {
-            calendars.push(ics_calendar(cal))
+    if period_arg == "todo" {
+        print_todos(calendars.iter().flat_map(|c| c.todos()));
+        return;
+    }
+    if period_arg == "export" {
+        if let Err(err) = export(&calendars) {
+            fail(&err);
         }
+        return;
+    }
+    if period_arg == "remind" {
+        remind(calendars, &paths, &conf.remind, || {
+            load(&paths, strict, recursive, merge, cache.as_ref(), None)
+        });
     }
 
-    let events = calendars
+    let (first, last) = match range {
+        Some(range) => range,
+        None => fail(&"invalid time frame"),
+    };
+    if agenda {
+        print_week(&calendars, first, &conf.emails, conf.week_start);
+        return;
+    }
+    if grid {
+        print_grid(&calendars, first, last, three, &conf.emails, conf.week_start);
+        return;
+    }
+    print_events(listed(&calendars, first, last, &conf.emails))
+}
+
+// The events in the window, without the ones declined by the user
+fn listed<'a>(
+    calendars: &'a [Calendar],
+    first: Date,
+    last: Date,
+    emails: &'a [String],
+) -> impl Iterator<Item = Event> + 'a {
+    calendars
         .iter()
-        .map(|c| c.iter())
+        .map(move |c| c.between(first, last))
         .kmerge()
-        .skip_while(|e| e.end_date() < first)
-        .take_while(|e| e.start <= last);
-    print_events(events)
-}
-
-fn period(arg: &str) -> (Date, Date) {
-    let days = match arg {
-        "all" => 0,
-        "day" => 1,
-        "week" => 7,
-        "month" => 30,
-        _ => panic!("Invalid time frame, try: all, day, week or month"),
-    };
-    if days == 0 {
-        (Date::new(), Date::max())
+        .filter(move |e| e.partstat(emails) != Some(PartStat::Declined))
+        .map(move |mut e| {
+            if e.partstat(emails) == Some(PartStat::Tentative) {
+                e.status = Some(Status::Tentative);
+            }
+            e
+        })
+}
+
+fn fail(err: &dyn std::fmt::Display) -> ! {
+    eprintln!("almanac: {}", err);
+    process::exit(1)
+}
+
+// The window of the time frame argument, or the one between --from and --to
+fn period(
+    arg: &str,
+    from: Option<String>,
+    to: Option<String>,
+    week_start: Weekday,
+) -> Result<Option<(Date, Date)>, EventError> {
+    let now = Date::now();
+    if from.is_some() || to.is_some() {
+        let range = format!("{}..{}", from.unwrap_or_default(), to.unwrap_or_default());
+        return parse_range(&range, now, week_start).map(Some);
+    }
+    match arg {
+        "todo" | "remind" | "export" => Ok(None),
+        "cal" => parse_range("month", now, week_start).map(Some),
+        "agenda" => parse_range("week", now, week_start).map(Some),
+        _ => parse_range(arg, now, week_start).map(Some),
+    }
+}
+
+fn load(
+    paths: &[String],
+    strict: bool,
+    recursive: bool,
+    merge: bool,
+    cache: Option<&Cache>,
+    range: Option<(Date, Date)>,
+) -> Result<Vec<Calendar>, EventError> {
+    let calendars = paths
+        .iter()
+        .map(|path| ics_calendar(path, strict, recursive, cache, range))
+        .collect::<Result<Vec<Calendar>, EventError>>()?;
+    if !merge {
+        return Ok(calendars);
+    }
+    let mut merged = Calendar::new();
+    for calendar in calendars {
+        merged.merge(calendar);
+    }
+    Ok(vec![merged])
+}
+
+fn ics_calendar(
+    file_path: &str,
+    strict: bool,
+    recursive: bool,
+    cache: Option<&Cache>,
+    range: Option<(Date, Date)>,
+) -> Result<Calendar, EventError> {
+    let calendar = if file_path == "-" {
+        let stdin = io::stdin();
+        let input = stdin.lock();
+        parse(input, strict)
+    } else if is_caldav(file_path) {
+        // the server only sends the events in range, it is not cached
+        CalDav::new(file_path)
+            .and_then(|caldav| caldav.fetch(range))
+            .and_then(|ics| parse(&ics[..], strict))
+    } else if is_url(file_path) {
+        url_calendar(file_path, strict, cache)
+    } else if Path::new(file_path).is_dir() {
+        let dir = Path::new(file_path);
+        if strict {
+            Calendar::parse_dir(dir, recursive)
+        } else {
+            Calendar::parse_dir_lenient(dir, recursive)
+        }
     } else {
-        let first = Date::now();
-        (first, first + Duration::days(days))
+        file_calendar(file_path, strict, cache)
+    }.map_err(|err| err.in_file(&without_userinfo(file_path)))?;
+
+    for warning in calendar.warnings() {
+        eprintln!("almanac: warning: {}: {}", without_userinfo(file_path), warning);
     }
+    Ok(calendar)
 }
 
-fn ics_calendar(file_path: &str) -> Calendar {
-    let file = File::open(file_path).unwrap();
-    let buf = BufReader::new(file);
-    Calendar::parse(buf).unwrap()
+fn file_calendar(path: &str, strict: bool, cache: Option<&Cache>) -> Result<Calendar, EventError> {
+    let stamp = file_stamp(path)?;
+    let source = fs::canonicalize(path)?;
+    cached(&source.to_string_lossy(), &stamp, strict, cache, || {
+        let file = File::open(path)?;
+        parse(BufReader::new(file), strict)
+    })
+}
+
+// The feed is only downloaded again if the server says it changed
+fn url_calendar(url: &str, strict: bool, cache: Option<&Cache>) -> Result<Calendar, EventError> {
+    let cached_feed = cache.and_then(|c| c.feed(url));
+    let feed = match (fetch(url, cached_feed.as_ref())?, cached_feed) {
+        (Fetched::Modified(feed), _) => {
+            if let Some(cache) = cache {
+                let _ = cache.store_feed(url, &feed);
+            }
+            feed
+        }
+        (Fetched::NotModified, Some(feed)) => feed,
+        (Fetched::NotModified, None) => return Ok(Calendar::new()),
+    };
+    cached(url, &feed_stamp(&feed), strict, cache, || parse(&feed.body[..], strict))
+}
+
+// Failing to write the cache is not an error, it will be parsed next time
+fn cached<F>(source: &str, stamp: &str, strict: bool, cache: Option<&Cache>, parse: F) -> Result<Calendar, EventError>
+where
+    F: FnOnce() -> Result<Calendar, EventError>,
+{
+    if let Some(calendar) = cache.and_then(|c| c.calendar(source, stamp, strict)) {
+        return Ok(calendar);
+    }
+    let calendar = parse()?;
+    if let Some(cache) = cache {
+        let _ = cache.store_calendar(source, stamp, strict, &calendar);
+    }
+    Ok(calendar)
 }
 
 fn print_events(events: impl Iterator<Item = Event>) {
@@ -73,9 +312,9 @@ fn print_events(events: impl Iterator<Item = Event>) {
     let mut unfinish: Vec<Event> = vec![];
 
     for event in events {
-        if !day.same_day(&event.start) {
+        if !day.same_local_day(&event.start) {
             if !unfinish.is_empty() {
-                while !day.same_day(&event.start) {
+                while !day.same_local_day(&event.start) {
                     day = day + Duration::days(1);
                     print_day(day);
                     for (i, event) in unfinish.clone().iter().enumerate() {
@@ -88,7 +327,7 @@ fn print_events(events: impl Iterator<Item = Event>) {
                     }
                 }
             } else {
-                day = event.start.clone();
+                day = event.start;
                 print_day(day);
             }
         }
@@ -113,7 +352,7 @@ fn print_events(events: impl Iterator<Item = Event>) {
             }
         }
     }
-    println!("");
+    println!();
 }
 
 fn print_day(date: Date) {
@@ -125,29 +364,564 @@ fn print_event(event: &Event, ustart: bool, uend: bool) {
         "-----".to_string()
     } else {
         match event.start {
-            Date::Time(_) => event.start.format("%R"),
             Date::AllDay(_) => "-----".to_string(),
+            _ => event.start.format("%R"),
         }
     };
     let end = if uend {
         "-----".to_string()
     } else {
         match event.end_date() {
-            Date::Time(_) => event.end_date().format("%R"),
             Date::AllDay(_) => "-----".to_string(),
+            _ => event.end_date().format("%R"),
         }
     };
 
+    let summary = if event.status == Some(Status::Tentative) {
+        format!("{} (tentative)", event.summary).italic()
+    } else {
+        event.summary.normal()
+    };
     println!(
         "    {}-{} {} {}",
         start.yellow(),
         end.yellow(),
-        event.summary,
+        summary,
         event.location.purple()
     );
 
     if !event.description.is_empty() {
-        let description = str::replace(&event.description, "\\n", &format!("\n{}", " ".repeat(16)));
+        let description = str::replace(&event.description, "\n", &format!("\n{}", " ".repeat(16)));
         println!("{}{}", " ".repeat(16), description.cyan());
     }
 }
+
+// Lines of a month in the grid, title and weekdays included
+const MONTH_LINES: usize = 8;
+
+// The month of the time frame as a grid of weeks like cal(1), with the
+// months before and after it with three. Today is reversed and the days with
+// events are highlighted, the events of a time frame of a day are listed
+// under the grid.
+fn print_grid(calendars: &[Calendar], first: Date, last: Date, three: bool, emails: &[String], week_start: Weekday) {
+    let months = grid_months(first, three);
+    let (start, end) = grid_window(first, three);
+    let events: Vec<Event> = listed(calendars, start, end, emails).collect();
+
+    let mut busy = HashSet::new();
+    for event in &events {
+        let end = event.end_date().local();
+        let mut day = event.start.local().date();
+        loop {
+            busy.insert(day);
+            day = day.succ_opt().unwrap();
+            if day.and_time(NaiveTime::MIN) >= end {
+                break;
+            }
+        }
+    }
+
+    let today = Date::now().local().date();
+    let grids: Vec<Vec<String>> = months
+        .iter()
+        .map(|month| month_grid(*month, today, &busy, week_start))
+        .collect();
+    for line in 0..MONTH_LINES {
+        println!("{}", grids.iter().map(|grid| &grid[line]).join("  ").trim_end());
+    }
+
+    if last - first <= Duration::days(1) {
+        print_events(events.into_iter().filter(|e| e.overlaps(first, last)));
+    }
+}
+
+// The first day of the months in the grid
+fn grid_months(first: Date, three: bool) -> Vec<NaiveDate> {
+    let month = first.local().date().with_day(1).unwrap();
+    if three {
+        vec![month - Months::new(1), month, month + Months::new(1)]
+    } else {
+        vec![month]
+    }
+}
+
+fn grid_window(first: Date, three: bool) -> (Date, Date) {
+    let months = grid_months(first, three);
+    let start = Date::Floating(months[0].and_time(NaiveTime::MIN));
+    let end = Date::Floating((months[months.len() - 1] + Months::new(1)).and_time(NaiveTime::MIN));
+    (start, end)
+}
+
+// Every line is 20 columns wide, so months can be put side by side
+fn month_grid(month: NaiveDate, today: NaiveDate, busy: &HashSet<NaiveDate>, week_start: Weekday) -> Vec<String> {
+    let title = format!("{:^20}", month.format("%B %Y").to_string());
+    let weekdays = (0..7)
+        .scan(week_start, |weekday, _| {
+            let name = weekday.to_string()[..2].to_string();
+            *weekday = weekday.succ();
+            Some(name)
+        })
+        .join(" ");
+    let mut lines = vec![title.bold().to_string(), weekdays];
+
+    let blank = (7 + month.weekday().num_days_from_monday() - week_start.num_days_from_monday()) % 7;
+    let mut days = vec!["  ".to_string(); blank as usize];
+    let mut day = month;
+    while day.month() == month.month() {
+        let mut cell = format!("{:>2}", day.day()).normal();
+        if busy.contains(&day) {
+            cell = cell.yellow().bold();
+        }
+        if day == today {
+            cell = cell.reversed();
+        }
+        days.push(cell.to_string());
+        day = day.succ_opt().unwrap();
+    }
+    while !days.len().is_multiple_of(7) {
+        days.push("  ".to_string());
+    }
+    lines.extend(days.chunks(7).map(|week| week.join(" ")));
+    lines.resize(MONTH_LINES, " ".repeat(20));
+    lines
+}
+
+// The week of the table, from the first day of the week on
+fn week_window(first: Date, week_start: Weekday) -> (Date, Date) {
+    let day = first.local().date();
+    let behind = (7 + day.weekday().num_days_from_monday() - week_start.num_days_from_monday()) % 7;
+    let start = Date::Floating((day - Duration::days(i64::from(behind))).and_time(NaiveTime::MIN));
+    (start, start + Duration::weeks(1))
+}
+
+// Columns of the hours in the week table
+const HOUR_WIDTH: usize = 5;
+
+// The week of the time frame as a table with a column for each day and a row
+// for each hour, the working hours and any other with events. Events at the
+// same time are put side by side in the day and all day events go in rows
+// over the hours. The table takes the width of the terminal.
+fn print_week(calendars: &[Calendar], first: Date, emails: &[String], week_start: Weekday) {
+    let (start, end) = week_window(first, week_start);
+    let monday = start.naive().date();
+    let days: Vec<NaiveDate> = (0..7).map(|i| monday + Duration::days(i)).collect();
+    let events: Vec<Event> = listed(calendars, start, end, emails).collect();
+    let (all_day, timed): (Vec<&Event>, Vec<&Event>) = events
+        .iter()
+        .partition(|e| matches!(e.start, Date::AllDay(_)));
+
+    let width = terminal_size().map_or(80, |(Width(width), _)| usize::from(width));
+    let column = cmp::max(width.saturating_sub(HOUR_WIDTH + 7) / 7, 5);
+    let separator = format!("{}{}", "-".repeat(HOUR_WIDTH), format!("+{}", "-".repeat(column)).repeat(7));
+
+    let today = Date::now().local().date();
+    let header = days.iter().map(|day| {
+        let name = fit(&day.format("%a %e").to_string(), column);
+        if *day == today {
+            name.bold().reversed()
+        } else {
+            name.bold()
+        }
+    });
+    println!("{}|{}", " ".repeat(HOUR_WIDTH), header.format("|"));
+    println!("{}", separator);
+
+    let all_day: Vec<Vec<&Event>> = days
+        .iter()
+        .map(|day| all_day.iter().cloned().filter(|e| covers(e, *day)).collect())
+        .collect();
+    let rows = all_day.iter().map(Vec::len).max().unwrap_or(0);
+    for row in 0..rows {
+        let cells = all_day.iter().map(|events| match events.get(row) {
+            Some(event) => summary(event, &fit(&event.summary, column)),
+            None => " ".repeat(column).normal(),
+        });
+        println!("{}|{}", " ".repeat(HOUR_WIDTH), cells.format("|"));
+    }
+    if rows > 0 {
+        println!("{}", separator);
+    }
+
+    let columns: Vec<Vec<Vec<Slot>>> = days.iter().map(|day| lanes(&timed, *day)).collect();
+    let slots = || columns.iter().flat_map(|lanes| lanes.iter().flatten());
+    let first_hour = slots().map(|slot| slot.from.hour()).min().map_or(8, |hour| cmp::min(hour, 8));
+    let last_hour = slots().map(|slot| end_hour(slot.to)).max().map_or(18, |hour| cmp::max(hour, 18));
+    for hour in first_hour..last_hour {
+        let cells = columns.iter().zip(&days).map(|(lanes, day)| {
+            let from = day.and_hms_opt(hour, 0, 0).unwrap();
+            hour_cell(lanes, from, hour == first_hour, column)
+        });
+        println!("{:<width$}|{}", format!("{:02}:00", hour), cells.format("|"), width = HOUR_WIDTH);
+    }
+}
+
+// The part of a timed event that falls in a day, in local time
+struct Slot<'a> {
+    event: &'a Event,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+}
+
+// The events of the day as lanes, each one with events that don't overlap,
+// an event goes to the first lane that is free at its start
+fn lanes<'a>(events: &[&'a Event], day: NaiveDate) -> Vec<Vec<Slot<'a>>> {
+    let midnight = day.and_time(NaiveTime::MIN);
+    let next = midnight + Duration::days(1);
+    let mut lanes: Vec<Vec<Slot>> = vec![];
+    for event in events {
+        let (start, end) = (event.start.local(), event.end_date().local());
+        if start >= next || (start < midnight && end <= midnight) {
+            continue;
+        }
+        let from = cmp::max(start, midnight);
+        // events without duration take a minute
+        let to = cmp::min(cmp::max(end, from + Duration::minutes(1)), next);
+        let slot = Slot { event, from, to };
+        match lanes.iter_mut().find(|lane| lane.last().is_none_or(|last| last.to <= from)) {
+            Some(lane) => lane.push(slot),
+            None => lanes.push(vec![slot]),
+        }
+    }
+    lanes
+}
+
+fn end_hour(to: NaiveDateTime) -> u32 {
+    if to.time() == NaiveTime::MIN {
+        24
+    } else if to.minute() == 0 && to.second() == 0 {
+        to.hour()
+    } else {
+        to.hour() + 1
+    }
+}
+
+// Narrowest lane in the week table
+const LANE_WIDTH: usize = 3;
+
+// The lanes of a day in the hour from, side by side. The text of an event
+// wraps along the hours it takes, from its first row in the table. The lanes
+// that don't fit are counted in a +N marker at the end.
+fn hour_cell(lanes: &[Vec<Slot>], from: NaiveDateTime, first_row: bool, column: usize) -> String {
+    if lanes.is_empty() {
+        return " ".repeat(column);
+    }
+    let to = from + Duration::hours(1);
+    let in_hour = |lane: &Vec<Slot>| lane.iter().position(|slot| slot.from < to && slot.to > from);
+    let fitting = cmp::max((column + 1) / (LANE_WIDTH + 1), 1);
+    let (shown, hidden) = if lanes.len() > fitting {
+        lanes.split_at(fitting - 1)
+    } else {
+        (lanes, &[][..])
+    };
+    let count = shown.len() + usize::from(!hidden.is_empty());
+    let width = cmp::max(column.saturating_sub(count - 1) / count, 1);
+    let cells = shown.iter().map(|lane| match in_hour(lane).map(|i| &lane[i]) {
+        Some(slot) => {
+            let text = format!("{} {}", slot.from.format("%R"), slot.event.summary);
+            let row = if first_row || slot.from >= from {
+                0
+            } else {
+                (from.hour() - slot.from.hour()) as usize
+            };
+            let part: String = text.chars().skip(row * width).collect();
+            let part = if part.is_empty() { "|".to_string() } else { part };
+            summary(slot.event, &fit(&part, width)).to_string()
+        }
+        None => " ".repeat(width),
+    });
+    let mut cell = cells.format(" ").to_string();
+    if !hidden.is_empty() {
+        let more = hidden.iter().filter(|lane| in_hour(lane).is_some()).count();
+        let marker = if more > 0 { format!("+{}", more) } else { String::new() };
+        if !shown.is_empty() {
+            cell.push(' ');
+        }
+        cell += &fit(&marker, width);
+    }
+    let used = width * count + count - 1;
+    format!("{}{}", cell, " ".repeat(column.saturating_sub(used)))
+}
+
+// The all day event is on the day, DTEND is not included
+fn covers(event: &Event, day: NaiveDate) -> bool {
+    let start = event.start.local().date();
+    let end = event.end_date().local().date();
+    day == start || (day > start && day < end)
+}
+
+fn summary(event: &Event, text: &str) -> ColoredString {
+    if event.status == Some(Status::Tentative) {
+        text.yellow().italic()
+    } else {
+        text.yellow()
+    }
+}
+
+// The text cut or padded to width columns
+fn fit(text: &str, width: usize) -> String {
+    let text: String = text.chars().take(width).collect();
+    format!("{:<width$}", text, width = width)
+}
+
+fn print_todos<'a>(todos: impl Iterator<Item = &'a Todo>) {
+    let now = Date::now();
+    for todo in todos.filter(|t| t.is_open()).sorted() {
+        let due = match todo.due {
+            Some(due @ Date::AllDay(_)) => due.format("%a %b %e %Y      "),
+            Some(due) => due.format("%a %b %e %Y %R"),
+            None => " ".repeat(21),
+        };
+        let due = if todo.is_overdue(&now) {
+            due.red().bold()
+        } else {
+            due.yellow()
+        };
+        let priority = if todo.priority == 0 {
+            "  ".to_string()
+        } else {
+            format!("!{}", todo.priority)
+        };
+        let percent = if todo.percent_complete == 0 {
+            "".to_string()
+        } else {
+            format!("{}%", todo.percent_complete)
+        };
+
+        println!("    {} {} {} {}", due, priority.purple(), todo.summary, percent.cyan());
+        if !todo.description.is_empty() {
+            let description = str::replace(&todo.description, "\n", &format!("\n{}", " ".repeat(16)));
+            println!("{}{}", " ".repeat(16), description.cyan());
+        }
+    }
+}
+
+// Compressed inputs are detected by their content
+fn parse<B: BufRead>(buf: B, strict: bool) -> Result<Calendar, EventError> {
+    let buf = decompress(buf)?;
+    if strict {
+        Calendar::parse(buf)
+    } else {
+        Calendar::parse_lenient(buf)
+    }
+}
+
+// All the calendars written as a single one to stdout
+fn export(calendars: &[Calendar]) -> io::Result<()> {
+    let stdout = io::stdout();
+    Calendar::write_all(calendars, BufWriter::new(stdout.lock()))?.flush()
+}
+
+// Runs until killed, every alarm that goes off is printed or passed to the
+// command in the environment.
+// The calendars are loaded again when one of their files changes. The ones
+// from URLs are only fetched again then, and stdin can't be read twice, so
+// nothing is reloaded when it is one of them.
+fn remind<F>(mut calendars: Vec<Calendar>, paths: &[String], command: &str, load: F) -> !
+where
+    F: Fn() -> Result<Vec<Calendar>, EventError>,
+{
+    let stamps = || paths.iter().map(|path| file_stamp(path).ok()).collect::<Vec<_>>();
+    let watch = !paths.iter().any(|path| path == "-");
+    let mut stamp = stamps();
+    let mut last = Date::now();
+    loop {
+        thread::sleep(time::Duration::from_secs(30));
+        if watch && stamps() != stamp {
+            stamp = stamps();
+            match load() {
+                Ok(loaded) => calendars = loaded,
+                Err(err) => eprintln!("almanac: warning: {}", err),
+            }
+        }
+        let now = Date::now();
+        for (at, event) in alarms(&calendars, last, now) {
+            if command.is_empty() {
+                println!("{} {} {}", at.format("%R").yellow(), event.summary, event.location.purple());
+                continue;
+            }
+            let status = Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .env("ALMANAC_SUMMARY", &event.summary)
+                .env("ALMANAC_LOCATION", &event.location)
+                .env("ALMANAC_DESCRIPTION", &event.description)
+                .env("ALMANAC_START", event.start.format("%F %R"))
+                .env("ALMANAC_END", event.end_date().format("%F %R"))
+                .status();
+            if let Err(err) = status {
+                eprintln!("almanac: warning: {}: {}", command, err);
+            }
+        }
+        last = now;
+    }
+}
+
+// Alarms going off in (from, to], the events are looked for as far from the
+// window as the alarms can be from them
+fn alarms(calendars: &[Calendar], from: Date, to: Date) -> Vec<(Date, Event)> {
+    let margin = calendars.iter().map(Calendar::alarm_reach).max().unwrap_or_else(Duration::zero);
+    calendars
+        .iter()
+        .map(|c| c.between(from + -margin, to + margin))
+        .kmerge()
+        .flat_map(|event| {
+            event.alarms
+                .iter()
+                .flat_map(|alarm| alarm.times(&event))
+                .filter(|at| *at > from && *at <= to)
+                .map(|at| (at, event.clone()))
+                .collect::<Vec<_>>()
+        })
+        .sorted_by_key(|(at, _)| *at)
+        // absolute triggers are the same for every occurrence
+        .dedup_by(|(at1, e1), (at2, e2)| at1 == at2 && e1.uid == e2.uid && e1.summary == e2.summary)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::{NaiveDate, NaiveDateTime, Weekday};
+    use colored;
+
+    use super::{alarms, covers, end_hour, grid_window, hour_cell, lanes, month_grid, week_window};
+    use almanac::{Calendar, Date, Duration, End, Event};
+
+    fn grid(year: i32, month: u32, week_start: Weekday) -> Vec<String> {
+        let month = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+        let today = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        month_grid(month, today, &HashSet::new(), week_start)
+    }
+
+    fn day(year: i32, month: u32, day: u32) -> Date {
+        Date::Floating(NaiveDate::from_ymd_opt(year, month, day).unwrap().and_hms_opt(12, 0, 0).unwrap())
+    }
+
+    #[test]
+    fn leading_blanks() {
+        // February 2021 starts on a Monday
+        let monday = grid(2021, 2, Weekday::Mon);
+        assert_eq!(monday[1], "Mo Tu We Th Fr Sa Su");
+        assert!(monday[2].starts_with(" 1  2"));
+        let sunday = grid(2021, 2, Weekday::Sun);
+        assert_eq!(sunday[1], "Su Mo Tu We Th Fr Sa");
+        assert!(sunday[2].starts_with("    1  2"));
+        let saturday = grid(2021, 2, Weekday::Sat);
+        assert!(saturday[2].starts_with("       1  2"));
+    }
+
+    #[test]
+    fn six_weeks() {
+        // May 2021 starts on a Saturday and has 31 days
+        let may = grid(2021, 5, Weekday::Mon);
+        assert_eq!(may.len(), 8);
+        assert_eq!(may[7].trim(), "31");
+        let may = grid(2021, 5, Weekday::Sun);
+        assert_eq!(may[7].trim(), "30 31");
+        // and February 2021 fits in four
+        let february = grid(2021, 2, Weekday::Mon);
+        assert_eq!(february[5].trim(), "22 23 24 25 26 27 28");
+        assert_eq!(february[6].trim(), "");
+    }
+
+    #[test]
+    fn shown_windows() {
+        let (start, end) = grid_window(day(2021, 2, 15), true);
+        assert_eq!((start.format("%F"), end.format("%F")), ("2021-01-01".to_string(), "2021-04-01".to_string()));
+        let (start, end) = grid_window(day(2021, 2, 15), false);
+        assert_eq!((start.format("%F"), end.format("%F")), ("2021-02-01".to_string(), "2021-03-01".to_string()));
+        let (start, end) = week_window(day(2021, 2, 17), Weekday::Sun);
+        assert_eq!((start.format("%F"), end.format("%F")), ("2021-02-14".to_string(), "2021-02-21".to_string()));
+    }
+
+    fn event(start: &str, end: &str, summary: &str) -> Event {
+        let date = |s| Date::Floating(NaiveDateTime::parse_from_str(s, "%F %R").unwrap());
+        let mut event = Event::new();
+        event.start = date(start);
+        event.end = End::Date(date(end));
+        event.summary = summary.to_string();
+        event
+    }
+
+    fn hour(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2021, 2, day).unwrap().and_hms_opt(hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn overlaps_in_lanes() {
+        let (a, b, c) = (
+            event("2021-02-01 10:00", "2021-02-01 12:00", "a long meeting"),
+            event("2021-02-01 11:00", "2021-02-01 11:30", "b"),
+            event("2021-02-01 12:00", "2021-02-01 13:00", "c"),
+        );
+        let lanes = lanes(&[&a, &b, &c], hour(1, 0).date());
+        let summaries: Vec<Vec<&str>> = lanes
+            .iter()
+            .map(|lane| lane.iter().map(|slot| slot.event.summary.as_str()).collect())
+            .collect();
+        assert_eq!(summaries, vec![vec!["a long meeting", "c"], vec!["b"]]);
+
+        colored::control::set_override(false);
+        assert_eq!(hour_cell(&lanes, hour(1, 10), true, 20), format!("{:<9} {:<9} ", "10:00 a l", ""));
+        assert_eq!(hour_cell(&lanes, hour(1, 11), false, 20), format!("{:<9} {:<9} ", "ong meeti", "11:00 b"));
+        assert_eq!(hour_cell(&lanes, hour(1, 12), false, 20), format!("{:<9} {:<9} ", "12:00 c", ""));
+        assert_eq!(hour_cell(&lanes, hour(1, 13), false, 20), " ".repeat(20));
+    }
+
+    #[test]
+    fn too_many_lanes() {
+        let events: Vec<Event> = ["x", "y", "z"]
+            .iter()
+            .map(|summary| event("2021-02-01 10:00", "2021-02-01 11:00", summary))
+            .collect();
+        let events: Vec<&Event> = events.iter().collect();
+        let lanes = lanes(&events, hour(1, 0).date());
+        assert_eq!(lanes.len(), 3);
+        colored::control::set_override(false);
+        assert_eq!(hour_cell(&lanes, hour(1, 10), true, 7), "10: +2 ");
+        assert_eq!(hour_cell(&lanes, hour(1, 11), false, 7), " ".repeat(7));
+        assert_eq!(hour_cell(&lanes, hour(1, 10), true, 20).chars().count(), 20);
+    }
+
+    #[test]
+    fn across_midnight() {
+        let late = event("2021-02-01 23:00", "2021-02-02 01:30", "late");
+        let first = lanes(&[&late], hour(1, 0).date());
+        assert_eq!((first[0][0].from, first[0][0].to), (hour(1, 23), hour(2, 0)));
+        assert_eq!(end_hour(first[0][0].to), 24);
+        let second = lanes(&[&late], hour(2, 0).date());
+        assert_eq!(second[0][0].from, hour(2, 0));
+        assert_eq!(end_hour(second[0][0].to), 2);
+        assert!(lanes(&[&late], hour(3, 0).date()).is_empty());
+
+        // an all day event doesn't take the day of its DTEND
+        let mut trip = Event::new();
+        trip.start = Date::AllDay(hour(1, 0).date());
+        trip.end = End::Date(Date::AllDay(hour(3, 0).date()));
+        assert!(covers(&trip, hour(1, 0).date()));
+        assert!(covers(&trip, hour(2, 0).date()));
+        assert!(!covers(&trip, hour(3, 0).date()));
+    }
+
+    #[test]
+    fn far_alarms() {
+        let ics = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+DTSTART:20210301T100000Z\r
+SUMMARY:renewal\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+TRIGGER:-P30D\r
+END:VALARM\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let calendars = vec![Calendar::parse(ics.as_bytes()).unwrap()];
+        let start = calendars[0].iter().next().unwrap().start;
+        let from = start + -Duration::days(30) + -Duration::minutes(30);
+        let fired = alarms(&calendars, from, from + Duration::hours(1));
+        let fired: Vec<String> = fired.iter().map(|(at, e)| at.naive().format("%F %R ").to_string() + &e.summary).collect();
+        assert_eq!(fired, vec!["2021-01-30 10:00 renewal"]);
+    }
+}
@@ -1,238 +1,223 @@
+use std::cmp;
 use std::fmt;
-use std::str::FromStr;
-use std::ops::Add;
-use std::collections::HashMap;
+use std::iter;
 
-use chrono::{Duration, Weekday};
+use chrono::{Duration, NaiveDate};
+use itertools::Itertools;
 
 use date::Date;
 use event::{Event, End};
-use errors::EventError;
+use rrule::Rrule;
+use timezone::Timezone;
 
-pub type Byday = HashMap<Weekday, Vec<i32>>;
-
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Periodic {
     pub event: Event,
-    pub freq: Freq,
-    pub interval: i64,
-    pub count: Option<i64>,
-    pub until: Option<Date>,
-    pub byday: Option<Byday>,
-    pub bysetpos: i32,
-    pub wkst: Weekday,
+    pub rrule: Option<Rrule>,
+    pub rdates: Vec<Rdate>,
+    pub exdates: Vec<Date>,
+    pub overrides: Vec<Override>,
+    pub timezone: Option<Timezone>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rdate {
+    pub start: Date,
+    pub end: Option<End>,
 }
 
-#[derive(Debug)]
-pub enum Freq {
-    Secondly,
-    Minutely,
-    Hourly,
-    Daily,
-    Weekly,
-    Monthly,
-    Yearly,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Override {
+    pub recurrence_id: Date,
+    pub this_and_future: bool,
+    pub event: Event,
 }
 
 impl Periodic {
     pub fn new() -> Self {
         Self {
             event: Event::new(),
-            freq: Freq::Secondly,
-            interval: 1,
-            count: None,
-            until: None,
-            byday: None,
-            bysetpos: 0,
-            wkst: Weekday::Mon,
+            rrule: None,
+            rdates: vec![],
+            exdates: vec![],
+            overrides: vec![],
+            timezone: None,
         }
     }
 
-    pub fn set_param(&mut self, param: &str, value: &str) -> Result<(), EventError> {
-        match param {
-            "FREQ" => self.freq = value.parse()?,
-            "INTERVAL" => self.interval = value.parse()?,
-            "COUNT" => self.count = Some(value.parse()?),
-            "UNTIL" => self.until = Some(Date::parse(&value, "")?),
-            "BYDAY" => self.byday = Some(parse_byday(value)?),
-            "BYSETPOS" => self.bysetpos = value.parse()?,
-            "WKST" => self.wkst = parse_weekday(value)?,
-            _ => (),
+    pub fn is_excluded(&self, date: &Date) -> bool {
+        self.exdates.iter().any(|exdate| self.same_date(exdate, date))
+    }
+
+    pub fn is_overridden(&self, date: &Date) -> bool {
+        self.overrides
+            .iter()
+            .any(|o| !o.this_and_future && self.same_date(&o.recurrence_id, date))
+    }
+
+    // A DATE matches any occurrence on that day
+    fn same_date(&self, date: &Date, occurrence: &Date) -> bool {
+        match *date {
+            Date::AllDay(day) => day == self.wall_date(occurrence),
+            _ => date == occurrence,
         }
-        Ok(())
     }
 
-    pub fn iter<'a>(&'a self) -> Iter<'a> {
-        Iter {
-            periodic: self,
-            start: self.event.start,
-            end: self.event.end_date(),
-            count: 0,
+    // The day of an occurrence in the zone of the series, not in the local
+    // one. With a VTIMEZONE the occurrences are kept in UTC.
+    fn wall_date(&self, occurrence: &Date) -> NaiveDate {
+        match (&self.timezone, occurrence) {
+            (Some(tz), &Date::Time(_)) => tz.to_local(occurrence).date(),
+            _ => occurrence.naive().date(),
         }
     }
-}
 
-pub struct Iter<'a> {
-    periodic: &'a Periodic,
-    start: Date,
-    end: Date,
-    count: i64,
-}
+    // A rule with no COUNT nor UNTIL never ends
+    pub fn is_unbounded(&self) -> bool {
+        self.rrule.as_ref().is_some_and(|r| r.count.is_none() && r.until.is_none())
+    }
 
-impl<'a> Iterator for Iter<'a> {
-    type Item = Event;
+    pub fn iter<'a>(&'a self) -> Iter<'a> {
+        self.expand(None)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let p = self.periodic;
+    // The occurrences that overlap [start, end). The rule jumps to the
+    // window instead of stepping through every occurrence before it.
+    pub fn occurrences_between<'a>(&'a self, start: Date, end: Date) -> impl Iterator<Item = Event> + 'a {
+        self.expand(Some(start))
+            .take_while(move |e| e.start < end)
+            .filter(move |e| e.overlaps(start, end))
+    }
 
-        if (p.until.is_some() && self.start <= p.until.unwrap()) ||
-            (p.count.is_some() && self.count >= p.count.unwrap())
-        {
-            return None;
+    fn expand<'a>(&'a self, skip: Option<Date>) -> Iter<'a> {
+        // Every THISANDFUTURE override starts a new segment of occurrences
+        // that takes its properties and its time shift.
+        let ranges: Vec<&Override> = self.overrides
+            .iter()
+            .filter(|o| o.this_and_future)
+            .sorted_by_key(|o| o.recurrence_id)
+            .collect();
+
+        let mut segments: Vec<Box<dyn Iterator<Item = Event> + 'a>> = vec![];
+        let first_range = ranges.first().map(|o| o.recurrence_id);
+        segments.push(Box::new(self.segment(None, first_range, &self.event, Duration::zero(), skip)));
+        for (i, range) in ranges.iter().enumerate() {
+            let to = ranges.get(i + 1).map(|o| o.recurrence_id);
+            let shift = range.event.start - range.recurrence_id;
+            segments.push(Box::new(self.segment(Some(range.recurrence_id), to, &range.event, shift, skip)));
         }
 
-        let mut event = p.event.clone();
-        event.start = self.start;
-        event.end = End::Date(self.end);
+        let single: Vec<Event> = self.overrides
+            .iter()
+            .filter(|o| !o.this_and_future)
+            .map(|o| o.event.clone())
+            .sorted()
+            .collect();
+        segments.push(Box::new(single.into_iter()));
 
-        let duration = self.next_duration(self.start);
-        self.start = self.start + duration;
-        self.end = self.end + duration;
-        self.count += 1;
+        Iter { occurrences: Box::new(segments.into_iter().kmerge()) }
+    }
 
-        Some(event)
+    fn segment<'a>(
+        &'a self,
+        from: Option<Date>,
+        to: Option<Date>,
+        template: &'a Event,
+        shift: Duration,
+        skip: Option<Date>,
+    ) -> impl Iterator<Item = Event> + 'a {
+        // without DTEND nor DURATION the end is left at the epoch
+        let duration = cmp::max(template.end_date() - template.start, Duration::zero());
+        // occurrences that end before skip are not needed
+        let jump = from.into_iter().chain(skip.map(|skip| skip + -(shift + duration))).max();
+        self.occurrences(jump)
+            .skip_while(move |o| from.is_some_and(|from| o.start < from))
+            .take_while(move |o| to.is_none_or(|to| o.start < to))
+            .filter(move |o| !self.is_overridden(&o.start))
+            .map(move |o| {
+                let mut event = template.clone();
+                event.start = o.start + shift;
+                event.end = match o.end {
+                    Some(end) if shift.is_zero() => end,
+                    _ => End::Date(event.start + duration),
+                };
+                event
+            })
     }
-}
 
-impl<'a> Iter<'a> {
-    fn next_duration(&self, date: Date) -> Duration {
-        let p = self.periodic;
-        match p.freq {
-            Freq::Secondly => Duration::seconds(p.interval),
-            Freq::Minutely => Duration::minutes(p.interval),
-            Freq::Hourly => Duration::hours(p.interval),
-            Freq::Daily => Duration::days(p.interval),
-            Freq::Weekly => {
-                match &p.byday {
-                    None => Duration::weeks(p.interval),
-                    Some(byday) => {
-                        let mut weekday = date.weekday().succ();
-                        let mut days = 1;
-                        if weekday == p.wkst {
-                            days += 7 * (p.interval - 1);
-                        }
-                        while !byday.contains_key(&weekday) {
-                            weekday = weekday.succ();
-                            days += 1;
-                            if weekday == p.wkst {
-                                days += 7 * (p.interval - 1);
-                            }
-                        }
-                        Duration::days(days)
-                    }
-                }
-            }
-            Freq::Monthly => {
-                match &p.byday {
-                    Some(byday) => {
-                        let mut next = date;
-                        if p.interval > 1 {
-                            next = next.with_day(1).unwrap();
-                            for _ in 1..p.interval {
-                                next = next.add(Duration::days(next.days_in_month().into()));
-                            }
-                        }
-                        loop {
-                            next = next.add(Duration::days(1));
-                            let (week, neg_week) = next.week_of_month();
-
-                            match byday.get(&next.weekday()) {
-                                Some(occurrences) =>
-                                    if p.bysetpos == week || p.bysetpos == neg_week
-                                            || occurrences.contains(&0)
-                                            || occurrences.contains(&week) || occurrences.contains(&neg_week) {
-                                        break;
-                                    }
-                                None => {}
-                            };
-                        };
-                        next - date
+    // The rule starts expanding near jump, a day before it to make up for
+    // the time zone offsets
+    fn occurrences<'a>(&'a self, jump: Option<Date>) -> impl Iterator<Item = Rdate> + 'a {
+        let start = self.event.start;
+        let ahead = jump.map_or_else(Duration::zero, |jump| jump - start - Duration::days(1));
+        let rule: Box<dyn Iterator<Item = Date> + 'a> = match self.rrule {
+            Some(ref rrule) => {
+                let until = rrule.until;
+                // with a VTIMEZONE the start is kept in UTC, expand it in
+                // the wall time of the zone
+                let dates: Box<dyn Iterator<Item = Date> + 'a> = match (&self.timezone, start) {
+                    (Some(tz), Date::Time(_)) => {
+                        let local = tz.to_local(&start);
+                        Box::new(rrule.iter_from(local, local + ahead).map(move |naive| tz.from_local(&naive)))
                     }
-                    None => {
-                        let new_date = if date.month() == 12 {
-                            date.with_month(1)
-                                .unwrap()
-                                .with_year(date.year() + 1)
-                                .unwrap()
-                        } else {
-                            date.with_month(date.month() + 1).unwrap()
-                        };
-                        new_date - date
+                    _ => {
+                        let naive = start.naive();
+                        Box::new(rrule.iter_from(naive, naive + ahead).map(move |naive| start.with_naive(naive)))
                     }
-                }
+                };
+                let dates = dates.take_while(move |date| match until {
+                        Some(Date::AllDay(until)) => self.wall_date(date) <= until,
+                        Some(until) => *date <= until,
+                        None => true,
+                    });
+                Box::new(dates)
             }
-            // TODO: byday...
-            Freq::Yearly => date.with_year(date.year() + 1).unwrap() - date,
-        }
+            None => Box::new(iter::once(start)),
+        };
+
+        rule.map(|start| Rdate { start, end: None })
+            .merge_by(self.rdates.iter().cloned(), |a, b| a.start < b.start)
+            .dedup_by(|a, b| a.start == b.start)
+            .filter(move |rdate| !self.is_excluded(&rdate.start))
     }
 }
 
-impl fmt::Display for Periodic {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self.freq)?;
-        if self.interval != 1 {
-            write!(f, "({})", self.interval)?;
-        }
-        write!(f, ": {}", self.event)?;
-        Ok(())
+impl Default for Periodic {
+    fn default() -> Self {
+        Periodic::new()
     }
 }
 
-impl FromStr for Freq {
-    type Err = EventError;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "SECONDLY" => Ok(Freq::Secondly),
-            "MINUTELY" => Ok(Freq::Minutely),
-            "HOURLY" => Ok(Freq::Hourly),
-            "DAILY" => Ok(Freq::Daily),
-            "WEEKLY" => Ok(Freq::Weekly),
-            "MONTHLY" => Ok(Freq::Monthly),
-            "YEARLY" => Ok(Freq::Yearly),
-            _ => Err(EventError::FreqError),
-        }
-    }
+pub struct Iter<'a> {
+    occurrences: Box<dyn Iterator<Item = Event> + 'a>,
 }
 
-fn parse_byday(s: &str) -> Result<Byday, EventError> {
-    let mut byday = Byday::new();
-    for v in s.split(",") {
-        let weekday = parse_weekday(&v[v.len() - 2..])?;
-        let occurrence = if v.len() > 2 {
-            v[..v.len() - 2].parse()?
-        } else {
-            0
-        };
-        match byday.get_mut(&weekday) {
-            Some(occurrences) => occurrences.push(occurrence),
-            None => {
-                byday.insert(weekday, vec![occurrence]);
-            }
-        };
+impl<'a> Iterator for Iter<'a> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.occurrences.next()
     }
-    Ok(byday)
 }
 
-fn parse_weekday(s: &str) -> Result<Weekday, EventError> {
-    match s {
-        "MO" => Ok(Weekday::Mon),
-        "TU" => Ok(Weekday::Tue),
-        "WE" => Ok(Weekday::Wed),
-        "TH" => Ok(Weekday::Thu),
-        "FR" => Ok(Weekday::Fri),
-        "SA" => Ok(Weekday::Sat),
-        "SU" => Ok(Weekday::Sun),
-        _ => Err(EventError::BydayError),
+impl fmt::Display for Periodic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.rrule {
+            Some(ref rrule) => {
+                write!(f, "{:?}", rrule.freq)?;
+                if rrule.interval != 1 {
+                    write!(f, "({})", rrule.interval)?;
+                }
+            }
+            None => write!(f, "Rdate")?,
+        }
+        if !self.rdates.is_empty() {
+            write!(f, " +{}", self.rdates.len())?;
+        }
+        if !self.overrides.is_empty() {
+            write!(f, " ~{}", self.overrides.len())?;
+        }
+        write!(f, ": {}", self.event)?;
+        Ok(())
     }
 }
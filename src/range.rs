@@ -0,0 +1,265 @@
+use std::convert::TryFrom;
+
+use chrono::{Datelike, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+
+use date::Date;
+use errors::EventError;
+
+// Parses a time frame into the window (start, end) it covers, relative to
+// now. The windows are in local time, weeks start on week_start:
+//
+//   all                              everything
+//   day, week, month                 today, this week or this month
+//   rolling day|week|month           the next 1, 7 or 30 days from now
+//   now, today, tomorrow, yesterday
+//   2026-11-01, 2026-11-01T10:00     a day or an instant
+//   +3d, -2w, +1m, +12h, +1y         from now to the offset
+//   tuesday, next tue, last friday   a day, today counts unless next/last
+//   this week, next month, last year
+//   from..to                         from the start of one to the end of the
+//                                    other, now or forever if left empty
+pub fn parse_range(expr: &str, now: Date, week_start: Weekday) -> Result<(Date, Date), EventError> {
+    let invalid = || EventError::RangeError(expr.to_string());
+    let expr = expr.trim().to_lowercase();
+    if let Some(pos) = expr.find("..") {
+        let (from, to) = (expr[..pos].trim(), expr[pos + 2..].trim());
+        let start = if from.is_empty() {
+            now
+        } else {
+            span(from, now, week_start).ok_or_else(invalid)?.0
+        };
+        let end = if to.is_empty() {
+            Date::max()
+        } else {
+            span(to, now, week_start).ok_or_else(invalid)?.1
+        };
+        if end < start {
+            return Err(invalid());
+        }
+        return Ok((start, end));
+    }
+    span(&expr, now, week_start).ok_or_else(invalid)
+}
+
+fn span(expr: &str, now: Date, week_start: Weekday) -> Option<(Date, Date)> {
+    let local = now.local();
+    let today = local.date();
+    let words: Vec<&str> = expr.split_whitespace().collect();
+    let day = |date: NaiveDate| {
+        let start = date.and_time(NaiveTime::MIN);
+        Some((Date::Floating(start), Date::Floating(start + Duration::days(1))))
+    };
+
+    match words[..] {
+        ["all"] => Some((Date::new(), Date::max())),
+        ["day"] | ["today"] => day(today),
+        ["week"] => span("this week", now, week_start),
+        ["month"] => span("this month", now, week_start),
+        ["rolling", "day"] => Some((now, now + Duration::days(1))),
+        ["rolling", "week"] => Some((now, now + Duration::days(7))),
+        ["rolling", "month"] => Some((now, now + Duration::days(30))),
+        ["now"] => Some((now, now)),
+        ["tomorrow"] => day(today.succ_opt()?),
+        ["yesterday"] => day(today.pred_opt()?),
+        [word] if word.starts_with('+') || word.starts_with('-') => {
+            let end = offset(local, word)?;
+            if end < local {
+                Some((Date::Floating(end), now))
+            } else {
+                Some((now, Date::Floating(end)))
+            }
+        }
+        [word] => match weekday(word) {
+            Some(weekday) => day(next_weekday(today, weekday, false)),
+            None => absolute(word),
+        },
+        [which, unit] => {
+            let step = match which {
+                "this" => 0,
+                "next" => 1,
+                "last" => -1,
+                _ => return None,
+            };
+            if let Some(weekday) = weekday(unit) {
+                return match step {
+                    0 => day(next_weekday(today, weekday, false)),
+                    1 => day(next_weekday(today, weekday, true)),
+                    _ => day(last_weekday(today, weekday)),
+                };
+            }
+            let (start, end) = match unit {
+                "week" => {
+                    let first = last_weekday(today.succ_opt()?, week_start);
+                    let start = first + Duration::weeks(step);
+                    (start, start + Duration::weeks(1))
+                }
+                "month" => {
+                    let first = today.with_day(1)?;
+                    let start = add_months(first, step)?;
+                    (start, add_months(start, 1)?)
+                }
+                "year" => {
+                    let start = NaiveDate::from_ymd_opt(today.year() + step as i32, 1, 1)?;
+                    (start, NaiveDate::from_ymd_opt(start.year() + 1, 1, 1)?)
+                }
+                _ => return None,
+            };
+            Some((
+                Date::Floating(start.and_time(NaiveTime::MIN)),
+                Date::Floating(end.and_time(NaiveTime::MIN)),
+            ))
+        }
+        _ => None,
+    }
+}
+
+// A day, or an instant if it has time
+fn absolute(word: &str) -> Option<(Date, Date)> {
+    if let Ok(date) = NaiveDate::parse_from_str(word, "%Y-%m-%d") {
+        let start = date.and_time(NaiveTime::MIN);
+        return Some((Date::Floating(start), Date::Floating(start + Duration::days(1))));
+    }
+    let time = NaiveDateTime::parse_from_str(word, "%Y-%m-%dt%H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(word, "%Y-%m-%dt%H:%M:%S"))
+        .ok()?;
+    Some((Date::Floating(time), Date::Floating(time)))
+}
+
+fn offset(local: NaiveDateTime, word: &str) -> Option<NaiveDateTime> {
+    let unit = word.chars().last()?;
+    let count: i64 = word[..word.len() - unit.len_utf8()].parse().ok()?;
+    match unit {
+        'h' => Some(local + Duration::hours(count)),
+        'd' => Some(local + Duration::days(count)),
+        'w' => Some(local + Duration::weeks(count)),
+        'm' => Some(add_months(local.date(), count)?.and_time(local.time())),
+        'y' => Some(add_months(local.date(), count * 12)?.and_time(local.time())),
+        _ => None,
+    }
+}
+
+fn add_months(date: NaiveDate, months: i64) -> Option<NaiveDate> {
+    let count = Months::new(u32::try_from(months.unsigned_abs()).ok()?);
+    if months < 0 {
+        date.checked_sub_months(count)
+    } else {
+        date.checked_add_months(count)
+    }
+}
+
+fn weekday(word: &str) -> Option<Weekday> {
+    word.parse().ok()
+}
+
+// The first day that is that weekday, from today or from tomorrow
+fn next_weekday(today: NaiveDate, day: Weekday, after_today: bool) -> NaiveDate {
+    let mut ahead = (7 + day.num_days_from_monday() as i64 - today.weekday().num_days_from_monday() as i64) % 7;
+    if ahead == 0 && after_today {
+        ahead = 7;
+    }
+    today + Duration::days(ahead)
+}
+
+fn last_weekday(today: NaiveDate, day: Weekday) -> NaiveDate {
+    let mut behind = (7 + today.weekday().num_days_from_monday() as i64 - day.num_days_from_monday() as i64) % 7;
+    if behind == 0 {
+        behind = 7;
+    }
+    today - Duration::days(behind)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, Weekday};
+
+    use super::parse_range;
+    use date::Date;
+
+    // Wednesday 2026-10-14 at 15:30
+    fn now() -> Date {
+        Date::Floating(NaiveDate::from_ymd_opt(2026, 10, 14).unwrap().and_hms_opt(15, 30, 0).unwrap())
+    }
+
+    fn range(expr: &str) -> (String, String) {
+        let (start, end) = parse_range(expr, now(), Weekday::Mon).unwrap();
+        (start.format("%F %R"), end.format("%F %R"))
+    }
+
+    fn window(start: &str, end: &str) -> (String, String) {
+        (start.to_string(), end.to_string())
+    }
+
+    #[test]
+    fn days() {
+        assert_eq!(range("today"), window("2026-10-14 00:00", "2026-10-15 00:00"));
+        assert_eq!(range("Tomorrow"), window("2026-10-15 00:00", "2026-10-16 00:00"));
+        assert_eq!(range("2026-11-01"), window("2026-11-01 00:00", "2026-11-02 00:00"));
+        assert_eq!(range("2026-11-01T10:00"), window("2026-11-01 10:00", "2026-11-01 10:00"));
+    }
+
+    #[test]
+    fn weekdays() {
+        assert_eq!(range("tuesday").0, "2026-10-20 00:00");
+        assert_eq!(range("wed").0, "2026-10-14 00:00");
+        assert_eq!(range("next wednesday").0, "2026-10-21 00:00");
+        assert_eq!(range("next tue").0, "2026-10-20 00:00");
+        assert_eq!(range("last wednesday").0, "2026-10-07 00:00");
+        assert_eq!(range("last friday").0, "2026-10-09 00:00");
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(range("+3d"), window("2026-10-14 15:30", "2026-10-17 15:30"));
+        assert_eq!(range("-2w"), window("2026-09-30 15:30", "2026-10-14 15:30"));
+        assert_eq!(range("+1m").1, "2026-11-14 15:30");
+        assert_eq!(range("+12h").1, "2026-10-15 03:30");
+    }
+
+    #[test]
+    fn units() {
+        assert_eq!(range("this week"), window("2026-10-12 00:00", "2026-10-19 00:00"));
+        assert_eq!(range("last week"), window("2026-10-05 00:00", "2026-10-12 00:00"));
+        assert_eq!(range("next month"), window("2026-11-01 00:00", "2026-12-01 00:00"));
+        assert_eq!(range("last year"), window("2025-01-01 00:00", "2026-01-01 00:00"));
+    }
+
+    #[test]
+    fn aligned() {
+        assert_eq!(range("day"), window("2026-10-14 00:00", "2026-10-15 00:00"));
+        assert_eq!(range("week"), window("2026-10-12 00:00", "2026-10-19 00:00"));
+        assert_eq!(range("month"), window("2026-10-01 00:00", "2026-11-01 00:00"));
+        let (start, end) = parse_range("week", now(), Weekday::Sun).unwrap();
+        assert_eq!(start.format("%F"), "2026-10-11");
+        assert_eq!(end.format("%F"), "2026-10-18");
+        let (start, _) = parse_range("week", now(), Weekday::Wed).unwrap();
+        assert_eq!(start.format("%F"), "2026-10-14");
+
+        let february = Date::Floating(NaiveDate::from_ymd_opt(2028, 2, 10).unwrap().and_hms_opt(9, 0, 0).unwrap());
+        let (_, end) = parse_range("month", february, Weekday::Mon).unwrap();
+        assert_eq!(end.format("%F"), "2028-03-01");
+    }
+
+    #[test]
+    fn rolling() {
+        assert_eq!(range("rolling day"), window("2026-10-14 15:30", "2026-10-15 15:30"));
+        assert_eq!(range("rolling week").1, "2026-10-21 15:30");
+        assert_eq!(range("rolling month").1, "2026-11-13 15:30");
+    }
+
+    #[test]
+    fn from_to() {
+        assert_eq!(range("2026-11-01..2026-11-15"), window("2026-11-01 00:00", "2026-11-16 00:00"));
+        assert_eq!(range("wed..fri"), window("2026-10-14 00:00", "2026-10-17 00:00"));
+        assert_eq!(range("..+3d"), window("2026-10-14 15:30", "2026-10-17 15:30"));
+        assert_eq!(range("last week..").0, "2026-10-05 00:00");
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(parse_range("someday", now(), Weekday::Mon).is_err());
+        assert!(parse_range("+3x", now(), Weekday::Mon).is_err());
+        assert!(parse_range("next decade", now(), Weekday::Mon).is_err());
+        assert!(parse_range("2026-11-15..2026-11-01", now(), Weekday::Mon).is_err());
+        assert!(parse_range("monday..friday", now(), Weekday::Mon).is_err());
+    }
+}
@@ -0,0 +1,1027 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono_tz::UTC;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Weekday};
+
+use date::{Date, days_in_month};
+use errors::EventError;
+
+// Rules that never match a valid date would expand forever, stop at the
+// year of Date::max().
+const MAX_YEAR: i32 = 2286;
+
+pub type Byday = Vec<(i32, Weekday)>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rrule {
+    pub freq: Freq,
+    pub interval: i64,
+    pub count: Option<i64>,
+    pub until: Option<Date>,
+    pub bysecond: Vec<u32>,
+    pub byminute: Vec<u32>,
+    pub byhour: Vec<u32>,
+    pub byday: Byday,
+    pub bymonthday: Vec<i32>,
+    pub byyearday: Vec<i32>,
+    pub byweekno: Vec<i32>,
+    pub bymonth: Vec<u32>,
+    pub bysetpos: Vec<i32>,
+    pub wkst: Weekday,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Freq {
+    Secondly,
+    Minutely,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl Rrule {
+    pub fn new() -> Self {
+        Self {
+            freq: Freq::Secondly,
+            interval: 1,
+            count: None,
+            until: None,
+            bysecond: vec![],
+            byminute: vec![],
+            byhour: vec![],
+            byday: vec![],
+            bymonthday: vec![],
+            byyearday: vec![],
+            byweekno: vec![],
+            bymonth: vec![],
+            bysetpos: vec![],
+            wkst: Weekday::Mon,
+        }
+    }
+
+    // Values out of range are rejected, they never match and the rule
+    // would expand until MAX_YEAR looking for them
+    pub fn set_param(&mut self, param: &str, value: &str) -> Result<(), EventError> {
+        let invalid = || EventError::RuleError(format!("{}={}", param, value));
+        match param {
+            "FREQ" => self.freq = value.parse()?,
+            "INTERVAL" => {
+                self.interval = value.parse()?;
+                if self.interval < 1 {
+                    return Err(invalid());
+                }
+            }
+            "COUNT" => self.count = Some(value.parse()?),
+            "UNTIL" => self.until = Some(Date::parse(value, "")?),
+            "BYSECOND" => self.bysecond = bounded(parse_list(value)?, 60).ok_or_else(invalid)?,
+            "BYMINUTE" => self.byminute = bounded(parse_list(value)?, 59).ok_or_else(invalid)?,
+            "BYHOUR" => self.byhour = bounded(parse_list(value)?, 23).ok_or_else(invalid)?,
+            "BYDAY" => {
+                self.byday = parse_byday(value)?;
+                if self.byday.iter().any(|&(n, _)| n.abs() > 53) {
+                    return Err(invalid());
+                }
+            }
+            "BYMONTHDAY" => self.bymonthday = signed(parse_list(value)?, 31).ok_or_else(invalid)?,
+            "BYYEARDAY" => self.byyearday = signed(parse_list(value)?, 366).ok_or_else(invalid)?,
+            "BYWEEKNO" => self.byweekno = signed(parse_list(value)?, 53).ok_or_else(invalid)?,
+            "BYMONTH" => {
+                self.bymonth = parse_list(value)?;
+                if self.bymonth.iter().any(|m| !(1..=12).contains(m)) {
+                    return Err(invalid());
+                }
+            }
+            "BYSETPOS" => self.bysetpos = signed(parse_list(value)?, 366).ok_or_else(invalid)?,
+            "WKST" => self.wkst = parse_weekday(value)?,
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Expand the rule in the local time of dtstart. dtstart is always the
+    /// first occurrence, UNTIL is left to the caller as it depends on the
+    /// time zone.
+    pub fn iter<'a>(&'a self, dtstart: NaiveDateTime) -> Iter<'a> {
+        let mut bymonth = self.bymonth.clone();
+        let mut bymonthday = self.bymonthday.clone();
+        let mut byday = self.byday.clone();
+        if self.byweekno.is_empty() && self.byyearday.is_empty() && bymonthday.is_empty() &&
+            byday.is_empty()
+        {
+            match self.freq {
+                Freq::Yearly => {
+                    if bymonth.is_empty() {
+                        bymonth = vec![dtstart.month()];
+                    }
+                    bymonthday = vec![dtstart.day() as i32];
+                }
+                Freq::Monthly => bymonthday = vec![dtstart.day() as i32],
+                Freq::Weekly => byday = vec![(0, dtstart.weekday())],
+                _ => (),
+            }
+        }
+
+        let byhour = default_time_part(&self.byhour, self.freq > Freq::Hourly, dtstart.hour());
+        let byminute = default_time_part(&self.byminute, self.freq > Freq::Minutely, dtstart.minute());
+        let bysecond = default_time_part(&self.bysecond, self.freq > Freq::Secondly, dtstart.second());
+
+        let date = dtstart.date();
+        let cursor = match self.freq {
+            Freq::Yearly => date.with_ordinal(1).unwrap().and_hms_opt(0, 0, 0),
+            Freq::Monthly => date.with_day(1).unwrap().and_hms_opt(0, 0, 0),
+            Freq::Weekly => {
+                let days = days_between(self.wkst, date.weekday());
+                (date - Duration::days(days)).and_hms_opt(0, 0, 0)
+            }
+            Freq::Daily => date.and_hms_opt(0, 0, 0),
+            Freq::Hourly => date.and_hms_opt(dtstart.hour(), 0, 0),
+            Freq::Minutely => date.and_hms_opt(dtstart.hour(), dtstart.minute(), 0),
+            Freq::Secondly => date.and_hms_opt(dtstart.hour(), dtstart.minute(), dtstart.second()),
+        };
+        // a rule that never matches only has dtstart
+        let cursor = cursor.filter(|_| self.reachable(dtstart));
+
+        Iter {
+            rrule: self,
+            dtstart,
+            bymonth,
+            bymonthday,
+            byday,
+            byhour,
+            byminute,
+            bysecond,
+            cursor,
+            buffer: VecDeque::new(),
+            count: 0,
+            dtstart_pending: true,
+        }
+    }
+}
+
+impl Rrule {
+    // Whether the rule has any time after dtstart, or it would look for one
+    // until MAX_YEAR. BYSECOND=60 is a leap second, which never happens.
+    // Under DAILY the cursor moves INTERVAL periods at a time, so it only
+    // lands on the times of the day a multiple of gcd(step, day) away from
+    // the one of dtstart.
+    fn reachable(&self, dtstart: NaiveDateTime) -> bool {
+        let seconds: Vec<u32> = self.bysecond.iter().cloned().filter(|&s| s < 60).collect();
+        if !self.bysecond.is_empty() && seconds.is_empty() {
+            return false;
+        }
+        let unit = match self.freq {
+            Freq::Hourly => 3600,
+            Freq::Minutely => 60,
+            Freq::Secondly => 1,
+            _ => return true,
+        };
+        if self.byhour.is_empty() && self.byminute.is_empty() && seconds.is_empty() {
+            return true;
+        }
+        let all = |values: &[u32], n: u32| if values.is_empty() { (0..n).collect() } else { values.to_vec() };
+        let hours = all(&self.byhour, 24);
+        let (minutes, seconds, start) = match self.freq {
+            Freq::Hourly => (vec![0], vec![0], dtstart.hour() * 3600),
+            Freq::Minutely => (all(&self.byminute, 60), vec![0], dtstart.num_seconds_from_midnight() / 60 * 60),
+            _ => (all(&self.byminute, 60), all(&seconds, 60), dtstart.num_seconds_from_midnight()),
+        };
+        let step = gcd(unit * self.interval, 86_400);
+        hours.iter().any(|h| {
+            minutes.iter().any(|m| {
+                seconds.iter().any(|s| (i64::from(h * 3600 + m * 60 + s) - i64::from(start)) % step == 0)
+            })
+        })
+    }
+
+    /// Like iter, but the expansion starts at the period that has from,
+    /// jumping over the ones before it. Occurrences before from might still
+    /// come out. With COUNT every occurrence has to be counted, so it steps
+    /// from dtstart.
+    pub fn iter_from<'a>(&'a self, dtstart: NaiveDateTime, from: NaiveDateTime) -> Iter<'a> {
+        let mut iter = self.iter(dtstart);
+        if self.count.is_none() && from > dtstart {
+            iter.dtstart_pending = false;
+            iter.jump(from);
+        }
+        iter
+    }
+}
+
+impl Default for Rrule {
+    fn default() -> Self {
+        Rrule::new()
+    }
+}
+
+pub struct Iter<'a> {
+    rrule: &'a Rrule,
+    dtstart: NaiveDateTime,
+    bymonth: Vec<u32>,
+    bymonthday: Vec<i32>,
+    byday: Byday,
+    byhour: Vec<u32>,
+    byminute: Vec<u32>,
+    bysecond: Vec<u32>,
+    cursor: Option<NaiveDateTime>,
+    buffer: VecDeque<NaiveDateTime>,
+    count: i64,
+    dtstart_pending: bool,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = NaiveDateTime;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(count) = self.rrule.count {
+            if self.count >= count {
+                return None;
+            }
+        }
+
+        let next = if self.dtstart_pending {
+            self.dtstart_pending = false;
+            self.dtstart
+        } else {
+            self.next_occurrence()?
+        };
+        self.count += 1;
+        Some(next)
+    }
+}
+
+impl<'a> Iter<'a> {
+    // Moves the cursor to the period that has from, in one step
+    fn jump(&mut self, from: NaiveDateTime) {
+        let cursor = match self.cursor {
+            Some(cursor) => cursor,
+            None => return,
+        };
+        let elapsed = from - cursor;
+        let periods = match self.rrule.freq {
+            Freq::Yearly => i64::from(from.year() - cursor.year()),
+            Freq::Monthly => {
+                i64::from(from.year() - cursor.year()) * 12 + i64::from(from.month0()) - i64::from(cursor.month0())
+            }
+            Freq::Weekly => elapsed.num_weeks(),
+            Freq::Daily => elapsed.num_days(),
+            Freq::Hourly => elapsed.num_hours(),
+            Freq::Minutely => elapsed.num_minutes(),
+            Freq::Secondly => elapsed.num_seconds(),
+        };
+        let steps = periods / self.rrule.interval;
+        if steps > 0 {
+            self.cursor = self.advance(cursor, steps);
+        }
+    }
+
+    fn next_occurrence(&mut self) -> Option<NaiveDateTime> {
+        while self.buffer.is_empty() {
+            let cursor = self.cursor?;
+            if cursor.year() > MAX_YEAR {
+                self.cursor = None;
+                return None;
+            }
+            self.expand_period(cursor);
+        }
+        self.buffer.pop_front()
+    }
+
+    fn expand_period(&mut self, cursor: NaiveDateTime) {
+        let freq = self.rrule.freq;
+        if freq <= Freq::Hourly && !self.day_matches(cursor.date()) {
+            // skip the rest of the day in one go
+            let left = 86_400 - i64::from(cursor.num_seconds_from_midnight());
+            let step = self.step_seconds();
+            self.cursor = self.advance(cursor, (left + step - 1) / step);
+            return;
+        }
+
+        let first = cursor.date();
+        let len = match freq {
+            Freq::Yearly => days_in_year(first.year()),
+            Freq::Monthly => days_in_month(first.year(), first.month()) as i64,
+            Freq::Weekly => 7,
+            _ => 1,
+        };
+        let days: Vec<NaiveDate> = (0..len)
+            .map(|i| first + Duration::days(i))
+            .filter(|d| self.day_matches(*d))
+            .collect();
+
+        let hours = self.time_part(&self.byhour, freq <= Freq::Hourly, cursor.hour());
+        let minutes = self.time_part(&self.byminute, freq <= Freq::Minutely, cursor.minute());
+        let seconds = self.time_part(&self.bysecond, freq == Freq::Secondly, cursor.second());
+        let mut times = vec![];
+        for h in &hours {
+            for m in &minutes {
+                for s in &seconds {
+                    if let Some(time) = NaiveTime::from_hms_opt(*h, *m, *s) {
+                        times.push(time);
+                    }
+                }
+            }
+        }
+
+        let mut set = vec![];
+        for day in &days {
+            for time in &times {
+                set.push(day.and_time(*time));
+            }
+        }
+        if !self.rrule.bysetpos.is_empty() {
+            set = select_positions(&set, &self.rrule.bysetpos);
+        }
+
+        let dtstart = self.dtstart;
+        self.buffer.extend(set.into_iter().filter(|d| *d > dtstart));
+        self.cursor = self.advance(cursor, 1);
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let rrule = self.rrule;
+        if !self.bymonth.is_empty() && !self.bymonth.contains(&date.month()) {
+            return false;
+        }
+        if !rrule.byweekno.is_empty() && rrule.freq == Freq::Yearly {
+            let (week, neg_week) = week_number(date, rrule.wkst);
+            if !rrule.byweekno.contains(&week) && !rrule.byweekno.contains(&neg_week) {
+                return false;
+            }
+        }
+        if !rrule.byyearday.is_empty() {
+            let day = date.ordinal() as i32;
+            let neg_day = day - days_in_year(date.year()) as i32 - 1;
+            if !rrule.byyearday.contains(&day) && !rrule.byyearday.contains(&neg_day) {
+                return false;
+            }
+        }
+        if !self.bymonthday.is_empty() {
+            let day = date.day() as i32;
+            let neg_day = day - days_in_month(date.year(), date.month()) as i32 - 1;
+            if !self.bymonthday.contains(&day) && !self.bymonthday.contains(&neg_day) {
+                return false;
+            }
+        }
+        if !self.byday.is_empty() {
+            let in_year = rrule.freq == Freq::Yearly && rrule.bymonth.is_empty();
+            let in_month = rrule.freq == Freq::Monthly || (rrule.freq == Freq::Yearly && !in_year);
+            let (nth, neg_nth) = if in_year {
+                let day = date.ordinal() as i64;
+                let len = days_in_year(date.year());
+                ((day - 1) / 7 + 1, -((len - day) / 7 + 1))
+            } else if in_month {
+                let day = date.day() as i64;
+                let len = days_in_month(date.year(), date.month()) as i64;
+                ((day - 1) / 7 + 1, -((len - day) / 7 + 1))
+            } else {
+                (0, 0)
+            };
+            let matches = self.byday.iter().any(|&(n, weekday)| {
+                weekday == date.weekday() &&
+                    (n == 0 || nth == 0 || i64::from(n) == nth || i64::from(n) == neg_nth)
+            });
+            if !matches {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn time_part(&self, values: &[u32], fixed: bool, current: u32) -> Vec<u32> {
+        if !fixed {
+            values.to_vec()
+        } else if values.is_empty() || values.contains(&current) {
+            vec![current]
+        } else {
+            vec![]
+        }
+    }
+
+    fn step_seconds(&self) -> i64 {
+        let unit = match self.rrule.freq {
+            Freq::Hourly => 3600,
+            Freq::Minutely => 60,
+            _ => 1,
+        };
+        unit * self.rrule.interval
+    }
+
+    fn advance(&self, cursor: NaiveDateTime, steps: i64) -> Option<NaiveDateTime> {
+        let n = self.rrule.interval * steps;
+        match self.rrule.freq {
+            Freq::Yearly => cursor.with_year(cursor.year() + n as i32),
+            Freq::Monthly => {
+                let months = i64::from(cursor.year()) * 12 + i64::from(cursor.month0()) + n;
+                NaiveDate::from_ymd_opt((months / 12) as i32, (months % 12) as u32 + 1, 1)?
+                    .and_hms_opt(0, 0, 0)
+            }
+            Freq::Weekly => cursor.checked_add_signed(Duration::weeks(n)),
+            Freq::Daily => cursor.checked_add_signed(Duration::days(n)),
+            Freq::Hourly => cursor.checked_add_signed(Duration::hours(n)),
+            Freq::Minutely => cursor.checked_add_signed(Duration::minutes(n)),
+            Freq::Secondly => cursor.checked_add_signed(Duration::seconds(n)),
+        }
+    }
+}
+
+// The RFC 5545 encoding of the rule, UNTIL in UTC unless it's a date or
+// floating
+impl fmt::Display for Rrule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FREQ={}", self.freq)?;
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={}", count)?;
+        }
+        match self.until {
+            Some(Date::Time(t)) => write!(f, ";UNTIL={}", t.with_timezone(&UTC).format("%Y%m%dT%H%M%SZ"))?,
+            Some(Date::Floating(t)) => write!(f, ";UNTIL={}", t.format("%Y%m%dT%H%M%S"))?,
+            Some(Date::AllDay(d)) => write!(f, ";UNTIL={}", d.format("%Y%m%d"))?,
+            None => (),
+        }
+        write_list(f, "BYSECOND", &self.bysecond)?;
+        write_list(f, "BYMINUTE", &self.byminute)?;
+        write_list(f, "BYHOUR", &self.byhour)?;
+        let byday: Vec<String> = self.byday
+            .iter()
+            .map(|&(n, weekday)| if n == 0 {
+                weekday_name(weekday).to_string()
+            } else {
+                format!("{}{}", n, weekday_name(weekday))
+            })
+            .collect();
+        write_list(f, "BYDAY", &byday)?;
+        write_list(f, "BYMONTHDAY", &self.bymonthday)?;
+        write_list(f, "BYYEARDAY", &self.byyearday)?;
+        write_list(f, "BYWEEKNO", &self.byweekno)?;
+        write_list(f, "BYMONTH", &self.bymonth)?;
+        write_list(f, "BYSETPOS", &self.bysetpos)?;
+        if self.wkst != Weekday::Mon {
+            write!(f, ";WKST={}", weekday_name(self.wkst))?;
+        }
+        Ok(())
+    }
+}
+
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter, name: &str, list: &[T]) -> fmt::Result {
+    if list.is_empty() {
+        return Ok(());
+    }
+    let list: Vec<String> = list.iter().map(T::to_string).collect();
+    write!(f, ";{}={}", name, list.join(","))
+}
+
+impl fmt::Display for Freq {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Freq::Secondly => "SECONDLY",
+            Freq::Minutely => "MINUTELY",
+            Freq::Hourly => "HOURLY",
+            Freq::Daily => "DAILY",
+            Freq::Weekly => "WEEKLY",
+            Freq::Monthly => "MONTHLY",
+            Freq::Yearly => "YEARLY",
+        })
+    }
+}
+
+impl FromStr for Freq {
+    type Err = EventError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "SECONDLY" => Ok(Freq::Secondly),
+            "MINUTELY" => Ok(Freq::Minutely),
+            "HOURLY" => Ok(Freq::Hourly),
+            "DAILY" => Ok(Freq::Daily),
+            "WEEKLY" => Ok(Freq::Weekly),
+            "MONTHLY" => Ok(Freq::Monthly),
+            "YEARLY" => Ok(Freq::Yearly),
+            _ => Err(EventError::FreqError(s.to_string())),
+        }
+    }
+}
+
+fn default_time_part(values: &[u32], expand: bool, dtstart: u32) -> Vec<u32> {
+    let mut values = values.to_vec();
+    if values.is_empty() && expand {
+        values.push(dtstart);
+    }
+    values.sort();
+    values.dedup();
+    values
+}
+
+fn select_positions(set: &[NaiveDateTime], positions: &[i32]) -> Vec<NaiveDateTime> {
+    let len = set.len() as i32;
+    let mut selected: Vec<NaiveDateTime> = positions
+        .iter()
+        .map(|&pos| if pos > 0 { pos - 1 } else { len + pos })
+        .filter(|&i| i >= 0 && i < len)
+        .map(|i| set[i as usize])
+        .collect();
+    selected.sort();
+    selected.dedup();
+    selected
+}
+
+fn days_in_year(year: i32) -> i64 {
+    if NaiveDate::from_ymd_opt(year, 2, 29).is_some() {
+        366
+    } else {
+        365
+    }
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn days_between(from: Weekday, to: Weekday) -> i64 {
+    (i64::from(to.num_days_from_monday()) - i64::from(from.num_days_from_monday()) + 7) % 7
+}
+
+// The first week of the year is the first one with at least 4 days on it,
+// weeks start on wkst.
+fn first_week(year: i32, wkst: Weekday) -> NaiveDate {
+    let jan4 = NaiveDate::from_ymd_opt(year, 1, 4).unwrap();
+    jan4 - Duration::days(days_between(wkst, jan4.weekday()))
+}
+
+fn week_number(date: NaiveDate, wkst: Weekday) -> (i32, i32) {
+    let mut year = date.year();
+    if date < first_week(year, wkst) {
+        year -= 1;
+    } else if date >= first_week(year + 1, wkst) {
+        year += 1;
+    }
+    let start = first_week(year, wkst);
+    let weeks = (first_week(year + 1, wkst) - start).num_days() / 7;
+    let week = (date - start).num_days() / 7 + 1;
+    (week as i32, (week - weeks - 1) as i32)
+}
+
+fn parse_list<T: FromStr>(s: &str) -> Result<Vec<T>, EventError>
+where
+    EventError: From<T::Err>,
+{
+    let mut list = vec![];
+    for v in s.split(',') {
+        list.push(v.trim_start_matches('+').parse()?);
+    }
+    Ok(list)
+}
+
+// Values from 0 to max
+fn bounded(list: Vec<u32>, max: u32) -> Option<Vec<u32>> {
+    if list.iter().all(|&v| v <= max) {
+        Some(list)
+    } else {
+        None
+    }
+}
+
+// Values from 1 to max, negative ones count from the end
+fn signed(list: Vec<i32>, max: i32) -> Option<Vec<i32>> {
+    if list.iter().all(|&v| v != 0 && v.abs() <= max) {
+        Some(list)
+    } else {
+        None
+    }
+}
+
+fn parse_byday(s: &str) -> Result<Byday, EventError> {
+    let mut byday = Byday::new();
+    for v in s.split(',') {
+        if v.len() < 2 {
+            return Err(EventError::BydayError(v.to_string()));
+        }
+        let weekday = parse_weekday(&v[v.len() - 2..])?;
+        let occurrence = if v.len() > 2 {
+            v[..v.len() - 2].trim_start_matches('+').parse()?
+        } else {
+            0
+        };
+        byday.push((occurrence, weekday));
+    }
+    Ok(byday)
+}
+
+fn parse_weekday(s: &str) -> Result<Weekday, EventError> {
+    match s {
+        "MO" => Ok(Weekday::Mon),
+        "TU" => Ok(Weekday::Tue),
+        "WE" => Ok(Weekday::Wed),
+        "TH" => Ok(Weekday::Thu),
+        "FR" => Ok(Weekday::Fri),
+        "SA" => Ok(Weekday::Sat),
+        "SU" => Ok(Weekday::Sun),
+        _ => Err(EventError::BydayError(s.to_string())),
+    }
+}
+
+fn weekday_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::Rrule;
+    use date::Date;
+    use event::End;
+    use periodic::Periodic;
+
+    // Examples from RFC 5545 section 3.8.5.3, all of them with
+    // DTSTART;TZID=America/New_York
+    fn expand(dtstart: &str, rule: &str, limit: usize) -> Vec<String> {
+        let mut periodic = Periodic::new();
+        periodic.rrule = Some(parse(rule));
+        periodic.event.start = Date::parse(dtstart, "America/New_York").unwrap();
+        periodic.event.end = End::Date(periodic.event.start);
+        periodic
+            .iter()
+            .take(limit)
+            .map(|e| e.start.naive().format("%Y%m%dT%H%M%S").to_string())
+            .collect()
+    }
+
+    fn parse(rule: &str) -> Rrule {
+        let mut rrule = Rrule::new();
+        for entry in rule.split(';') {
+            let p: Vec<&str> = entry.splitn(2, '=').collect();
+            rrule.set_param(p[0], p[1]).unwrap();
+        }
+        rrule
+    }
+
+    fn at(time: &str, days: &[&str]) -> Vec<String> {
+        days.iter().map(|d| format!("{}T{}", d, time)).collect()
+    }
+
+    #[test]
+    fn daily_count() {
+        let expected = at("090000", &["19970902", "19970903", "19970904", "19970905", "19970906",
+                                      "19970907", "19970908", "19970909", "19970910", "19970911"]);
+        assert_eq!(expand("19970902T090000", "FREQ=DAILY;COUNT=10", 100), expected);
+    }
+
+    #[test]
+    fn daily_until() {
+        let occurrences = expand("19970902T090000", "FREQ=DAILY;UNTIL=19971224T000000Z", 1000);
+        assert_eq!(occurrences.len(), 113);
+        assert_eq!(occurrences[0], "19970902T090000");
+        assert_eq!(occurrences[112], "19971223T090000");
+    }
+
+    #[test]
+    fn every_other_day() {
+        let expected = at("090000", &["19970902", "19970904", "19970906", "19970908"]);
+        assert_eq!(expand("19970902T090000", "FREQ=DAILY;INTERVAL=2", 4), expected);
+    }
+
+    #[test]
+    fn every_10_days() {
+        let expected = at("090000", &["19970902", "19970912", "19970922", "19971002", "19971012"]);
+        assert_eq!(expand("19970902T090000", "FREQ=DAILY;INTERVAL=10;COUNT=5", 100), expected);
+    }
+
+    #[test]
+    fn every_day_in_january() {
+        let yearly = expand("19980101T090000",
+                            "FREQ=YEARLY;UNTIL=20000131T140000Z;BYMONTH=1;BYDAY=SU,MO,TU,WE,TH,FR,SA",
+                            1000);
+        let daily = expand("19980101T090000", "FREQ=DAILY;UNTIL=20000131T140000Z;BYMONTH=1", 1000);
+        assert_eq!(yearly.len(), 93);
+        assert_eq!(yearly, daily);
+        assert_eq!(yearly[31], "19990101T090000");
+        assert_eq!(yearly[92], "20000131T090000");
+    }
+
+    #[test]
+    fn weekly_count() {
+        // crosses the end of DST but keeps the local time
+        let expected = at("090000", &["19970902", "19970909", "19970916", "19970923", "19970930",
+                                      "19971007", "19971014", "19971021", "19971028", "19971104"]);
+        assert_eq!(expand("19970902T090000", "FREQ=WEEKLY;COUNT=10", 100), expected);
+    }
+
+    #[test]
+    fn weekly_until() {
+        let occurrences = expand("19970902T090000", "FREQ=WEEKLY;UNTIL=19971224T000000Z", 100);
+        assert_eq!(occurrences.len(), 17);
+        assert_eq!(occurrences[16], "19971223T090000");
+    }
+
+    #[test]
+    fn weekly_tuesday_thursday() {
+        let expected = at("090000", &["19970902", "19970904", "19970909", "19970911", "19970916",
+                                      "19970918", "19970923", "19970925", "19970930", "19971002"]);
+        assert_eq!(expand("19970902T090000", "FREQ=WEEKLY;UNTIL=19971007T000000Z;WKST=SU;BYDAY=TU,TH", 100),
+                   expected);
+        assert_eq!(expand("19970902T090000", "FREQ=WEEKLY;COUNT=10;WKST=SU;BYDAY=TU,TH", 100),
+                   expected);
+    }
+
+    #[test]
+    fn every_other_week_monday_wednesday_friday() {
+        let expected = at("090000", &["19970901", "19970903", "19970905", "19970915", "19970917",
+                                      "19970919", "19970929", "19971001", "19971003", "19971013",
+                                      "19971015", "19971017", "19971027", "19971029", "19971031",
+                                      "19971110", "19971112", "19971114", "19971124", "19971126",
+                                      "19971128", "19971208", "19971210", "19971212", "19971222"]);
+        assert_eq!(expand("19970901T090000",
+                          "FREQ=WEEKLY;INTERVAL=2;UNTIL=19971224T000000Z;WKST=SU;BYDAY=MO,WE,FR",
+                          100),
+                   expected);
+    }
+
+    #[test]
+    fn every_other_week_tuesday_thursday() {
+        let expected = at("090000", &["19970902", "19970904", "19970916", "19970918", "19970930",
+                                      "19971002", "19971014", "19971016"]);
+        assert_eq!(expand("19970902T090000", "FREQ=WEEKLY;INTERVAL=2;COUNT=8;WKST=SU;BYDAY=TU,TH", 100),
+                   expected);
+    }
+
+    #[test]
+    fn monthly_first_friday() {
+        let expected = at("090000", &["19970905", "19971003", "19971107", "19971205", "19980102",
+                                      "19980206", "19980306", "19980403", "19980501", "19980605"]);
+        assert_eq!(expand("19970905T090000", "FREQ=MONTHLY;COUNT=10;BYDAY=1FR", 100), expected);
+        assert_eq!(expand("19970905T090000", "FREQ=MONTHLY;UNTIL=19971224T000000Z;BYDAY=1FR", 100),
+                   expected[..4].to_vec());
+    }
+
+    #[test]
+    fn every_other_month_first_and_last_sunday() {
+        let expected = at("090000", &["19970907", "19970928", "19971102", "19971130", "19980104",
+                                      "19980125", "19980301", "19980329", "19980503", "19980531"]);
+        assert_eq!(expand("19970907T090000", "FREQ=MONTHLY;INTERVAL=2;COUNT=10;BYDAY=1SU,-1SU", 100),
+                   expected);
+    }
+
+    #[test]
+    fn monthly_second_to_last_monday() {
+        let expected = at("090000", &["19970922", "19971020", "19971117", "19971222", "19980119",
+                                      "19980216"]);
+        assert_eq!(expand("19970922T090000", "FREQ=MONTHLY;COUNT=6;BYDAY=-2MO", 100), expected);
+    }
+
+    #[test]
+    fn monthly_third_to_last_day() {
+        let expected = at("090000", &["19970928", "19971029", "19971128", "19971229", "19980129",
+                                      "19980226"]);
+        assert_eq!(expand("19970928T090000", "FREQ=MONTHLY;BYMONTHDAY=-3", 6), expected);
+    }
+
+    #[test]
+    fn monthly_2nd_and_15th() {
+        let expected = at("090000", &["19970902", "19970915", "19971002", "19971015", "19971102",
+                                      "19971115", "19971202", "19971215", "19980102", "19980115"]);
+        assert_eq!(expand("19970902T090000", "FREQ=MONTHLY;COUNT=10;BYMONTHDAY=2,15", 100), expected);
+    }
+
+    #[test]
+    fn monthly_first_and_last_day() {
+        let expected = at("090000", &["19970930", "19971001", "19971031", "19971101", "19971130",
+                                      "19971201", "19971231", "19980101", "19980131", "19980201"]);
+        assert_eq!(expand("19970930T090000", "FREQ=MONTHLY;COUNT=10;BYMONTHDAY=1,-1", 100), expected);
+    }
+
+    #[test]
+    fn every_18_months() {
+        let expected = at("090000", &["19970910", "19970911", "19970912", "19970913", "19970914",
+                                      "19970915", "19990310", "19990311", "19990312", "19990313"]);
+        assert_eq!(expand("19970910T090000",
+                          "FREQ=MONTHLY;INTERVAL=18;COUNT=10;BYMONTHDAY=10,11,12,13,14,15",
+                          100),
+                   expected);
+    }
+
+    #[test]
+    fn every_tuesday_every_other_month() {
+        let expected = at("090000", &["19970902", "19970909", "19970916", "19970923", "19970930",
+                                      "19971104", "19971111", "19971118", "19971125", "19980106"]);
+        assert_eq!(expand("19970902T090000", "FREQ=MONTHLY;INTERVAL=2;BYDAY=TU", 10), expected);
+    }
+
+    #[test]
+    fn yearly_june_and_july() {
+        let expected = at("090000", &["19970610", "19970710", "19980610", "19980710", "19990610",
+                                      "19990710", "20000610", "20000710", "20010610", "20010710"]);
+        assert_eq!(expand("19970610T090000", "FREQ=YEARLY;COUNT=10;BYMONTH=6,7", 100), expected);
+    }
+
+    #[test]
+    fn every_other_year_first_quarter() {
+        let expected = at("090000", &["19970310", "19990110", "19990210", "19990310", "20010110",
+                                      "20010210", "20010310", "20030110", "20030210", "20030310"]);
+        assert_eq!(expand("19970310T090000", "FREQ=YEARLY;INTERVAL=2;COUNT=10;BYMONTH=1,2,3", 100),
+                   expected);
+    }
+
+    #[test]
+    fn every_third_year_by_yearday() {
+        let expected = at("090000", &["19970101", "19970410", "19970719", "20000101", "20000409",
+                                      "20000718", "20030101", "20030410", "20030719", "20060101"]);
+        assert_eq!(expand("19970101T090000", "FREQ=YEARLY;INTERVAL=3;COUNT=10;BYYEARDAY=1,100,200", 100),
+                   expected);
+    }
+
+    #[test]
+    fn every_20th_monday() {
+        let expected = at("090000", &["19970519", "19980518", "19990517"]);
+        assert_eq!(expand("19970519T090000", "FREQ=YEARLY;BYDAY=20MO", 3), expected);
+    }
+
+    #[test]
+    fn monday_of_week_20() {
+        let expected = at("090000", &["19970512", "19980511", "19990517"]);
+        assert_eq!(expand("19970512T090000", "FREQ=YEARLY;BYWEEKNO=20;BYDAY=MO", 3), expected);
+    }
+
+    #[test]
+    fn every_thursday_in_march() {
+        let expected = at("090000", &["19970313", "19970320", "19970327", "19980305", "19980312",
+                                      "19980319", "19980326", "19990304", "19990311", "19990318",
+                                      "19990325"]);
+        assert_eq!(expand("19970313T090000", "FREQ=YEARLY;BYMONTH=3;BYDAY=TH", 11), expected);
+    }
+
+    #[test]
+    fn every_thursday_in_summer() {
+        let expected = at("090000", &["19970605", "19970612", "19970619", "19970626", "19970703",
+                                      "19970710", "19970717", "19970724", "19970731", "19970807",
+                                      "19970814", "19970821", "19970828", "19980604"]);
+        assert_eq!(expand("19970605T090000", "FREQ=YEARLY;BYDAY=TH;BYMONTH=6,7,8", 14), expected);
+    }
+
+    #[test]
+    fn friday_13th() {
+        let expected = at("090000", &["19970902", "19980213", "19980313", "19981113", "19990813",
+                                      "20001013"]);
+        assert_eq!(expand("19970902T090000", "FREQ=MONTHLY;BYDAY=FR;BYMONTHDAY=13", 6), expected);
+    }
+
+    #[test]
+    fn saturday_after_first_sunday() {
+        let expected = at("090000", &["19970913", "19971011", "19971108", "19971213", "19980110",
+                                      "19980207", "19980307", "19980411", "19980509", "19980613"]);
+        assert_eq!(expand("19970913T090000", "FREQ=MONTHLY;BYDAY=SA;BYMONTHDAY=7,8,9,10,11,12,13", 10),
+                   expected);
+    }
+
+    #[test]
+    fn us_presidential_election_day() {
+        let expected = at("090000", &["19961105", "20001107", "20041102"]);
+        assert_eq!(expand("19961105T090000",
+                          "FREQ=YEARLY;INTERVAL=4;BYMONTH=11;BYDAY=TU;BYMONTHDAY=2,3,4,5,6,7,8",
+                          3),
+                   expected);
+    }
+
+    #[test]
+    fn third_weekday_instance() {
+        let expected = at("090000", &["19970904", "19971007", "19971106"]);
+        assert_eq!(expand("19970904T090000", "FREQ=MONTHLY;COUNT=3;BYDAY=TU,WE,TH;BYSETPOS=3", 100),
+                   expected);
+    }
+
+    #[test]
+    fn second_to_last_weekday() {
+        let expected = at("090000", &["19970929", "19971030", "19971127", "19971230", "19980129",
+                                      "19980226", "19980330"]);
+        assert_eq!(expand("19970929T090000", "FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-2", 7),
+                   expected);
+    }
+
+    #[test]
+    fn every_15_minutes() {
+        let expected = vec!["19970902T090000", "19970902T091500", "19970902T093000", "19970902T094500",
+                            "19970902T100000", "19970902T101500"];
+        assert_eq!(expand("19970902T090000", "FREQ=MINUTELY;INTERVAL=15;COUNT=6", 100), expected);
+    }
+
+    #[test]
+    fn every_hour_and_a_half() {
+        let expected = vec!["19970902T090000", "19970902T103000", "19970902T120000", "19970902T133000"];
+        assert_eq!(expand("19970902T090000", "FREQ=MINUTELY;INTERVAL=90;COUNT=4", 100), expected);
+    }
+
+    #[test]
+    fn every_20_minutes_office_hours() {
+        let daily = expand("19970902T090000",
+                           "FREQ=DAILY;BYHOUR=9,10,11,12,13,14,15,16;BYMINUTE=0,20,40",
+                           30);
+        let minutely = expand("19970902T090000",
+                              "FREQ=MINUTELY;INTERVAL=20;BYHOUR=9,10,11,12,13,14,15,16",
+                              30);
+        assert_eq!(daily, minutely);
+        assert_eq!(daily[1], "19970902T092000");
+        assert_eq!(daily[23], "19970902T164000");
+        assert_eq!(daily[24], "19970903T090000");
+    }
+
+    #[test]
+    fn wkst_changes_the_set() {
+        let monday = at("090000", &["19970805", "19970810", "19970819", "19970824"]);
+        assert_eq!(expand("19970805T090000", "FREQ=WEEKLY;INTERVAL=2;COUNT=4;BYDAY=TU,SU;WKST=MO", 100),
+                   monday);
+        let sunday = at("090000", &["19970805", "19970817", "19970819", "19970831"]);
+        assert_eq!(expand("19970805T090000", "FREQ=WEEKLY;INTERVAL=2;COUNT=4;BYDAY=TU,SU;WKST=SU", 100),
+                   sunday);
+    }
+
+    #[test]
+    fn invalid_dates_are_skipped() {
+        let expected = at("090000", &["20070115", "20070130", "20070215", "20070315", "20070330"]);
+        assert_eq!(expand("20070115T090000", "FREQ=MONTHLY;BYMONTHDAY=15,30;COUNT=5", 100), expected);
+    }
+
+    #[test]
+    fn impossible_rule_terminates() {
+        assert_eq!(expand("20070115T090000", "FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=30", 100).len(), 1);
+    }
+
+    #[test]
+    fn iter_from_jumps() {
+        let rules = [
+            "FREQ=DAILY;INTERVAL=3",
+            "FREQ=WEEKLY;INTERVAL=2;BYDAY=TU,TH;WKST=SU",
+            "FREQ=MONTHLY;BYDAY=-1FR",
+            "FREQ=YEARLY;INTERVAL=2;BYMONTH=3;BYDAY=1SU",
+            "FREQ=HOURLY;INTERVAL=5",
+            "FREQ=MINUTELY;INTERVAL=45;BYHOUR=9,10",
+        ];
+        let dtstart = NaiveDate::from_ymd_opt(1997, 9, 2).unwrap().and_hms_opt(9, 0, 0).unwrap();
+        let from = NaiveDate::from_ymd_opt(2019, 5, 17).unwrap().and_hms_opt(13, 0, 0).unwrap();
+        for rule in &rules {
+            let rrule = parse(rule);
+            let stepped: Vec<_> = rrule.iter(dtstart).skip_while(|d| *d < from).take(20).collect();
+            let jumped: Vec<_> = rrule.iter_from(dtstart, from).skip_while(|d| *d < from).take(20).collect();
+            assert_eq!(jumped, stepped, "{}", rule);
+        }
+    }
+
+    #[test]
+    fn out_of_range() {
+        let parts = [
+            ("BYHOUR", "24"),
+            ("BYMINUTE", "60"),
+            ("BYSECOND", "61"),
+            ("BYMONTH", "13"),
+            ("BYMONTH", "0"),
+            ("BYMONTHDAY", "0"),
+            ("BYMONTHDAY", "-32"),
+            ("BYYEARDAY", "367"),
+            ("BYWEEKNO", "54"),
+            ("BYSETPOS", "0"),
+            ("BYDAY", "54MO"),
+            ("INTERVAL", "0"),
+        ];
+        for &(param, value) in &parts {
+            assert!(Rrule::new().set_param(param, value).is_err(), "{}={}", param, value);
+        }
+        let mut rrule = Rrule::new();
+        rrule.set_param("BYHOUR", "0,23").unwrap();
+        rrule.set_param("BYMONTHDAY", "1,-31").unwrap();
+        rrule.set_param("BYDAY", "-53FR,MO").unwrap();
+    }
+
+    #[test]
+    fn never_matches() {
+        let dtstart = NaiveDate::from_ymd_opt(2019, 1, 1).unwrap().and_hms_opt(10, 0, 0).unwrap();
+        for rule in &["FREQ=SECONDLY;BYSECOND=60", "FREQ=MINUTELY;INTERVAL=2;BYMINUTE=1",
+                      "FREQ=DAILY;BYSECOND=60", "FREQ=HOURLY;INTERVAL=6;BYHOUR=9,11"] {
+            let rrule = parse(rule);
+            assert_eq!(rrule.iter(dtstart).take(3).collect::<Vec<_>>(), vec![dtstart], "{}", rule);
+        }
+        // the times that can be reached are still found
+        let times: Vec<String> = parse("FREQ=MINUTELY;INTERVAL=2;BYMINUTE=2,3;BYHOUR=11")
+            .iter(dtstart)
+            .take(3)
+            .map(|t| t.format("%d %H:%M").to_string())
+            .collect();
+        assert_eq!(times, vec!["01 10:00", "01 11:02", "02 11:02"]);
+    }
+}
@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Weekday};
+use chrono_tz::{OffsetComponents, Tz, UTC};
+use ical::parser::ical::component::{IcalTimeZone, IcalTimeZoneTransitionType};
+
+use date::{Date, days_in_month};
+use errors::EventError;
+use rrule::{Freq, Rrule};
+use writer::Writer;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timezone {
+    pub tzid: String,
+    observances: Vec<Observance>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Observance {
+    daylight: bool,
+    dtstart: NaiveDateTime,
+    #[serde(with = "::date::duration")]
+    offset_from: Duration,
+    #[serde(with = "::date::duration")]
+    offset_to: Duration,
+    rrule: Option<Rrule>,
+    rdates: Vec<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Timezones {
+    zones: HashMap<String, Timezone>,
+}
+
+impl Timezones {
+    pub fn new() -> Self {
+        Timezones { zones: HashMap::new() }
+    }
+
+    pub fn insert(&mut self, timezone: Timezone) {
+        self.zones.insert(timezone.tzid.clone(), timezone);
+    }
+
+    pub fn get(&self, tzid: &str) -> Option<&Timezone> {
+        self.zones.get(tzid)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Timezone> {
+        self.zones.values()
+    }
+
+    // Only when the calendar doesn't define the TZID we try to guess it from
+    // the IANA or Windows zone names.
+    pub fn parse_date(&self, value: &str, tzid: &str) -> Result<Date, EventError> {
+        match self.zones.get(tzid) {
+            Some(timezone) if value.contains('T') && !value.ends_with('Z') => {
+                Ok(timezone.from_local(&parse_naive(value)?))
+            }
+            _ => Date::parse(value, tzid),
+        }
+    }
+}
+
+impl Timezone {
+    pub fn parse(ical: &IcalTimeZone) -> Result<Self, EventError> {
+        let mut tzid = String::new();
+        for property in &ical.properties {
+            if property.name == "TZID" {
+                tzid = property.value.clone().unwrap_or_default();
+            }
+        }
+
+        let mut observances = vec![];
+        for transition in &ical.transitions {
+            let mut observance = Observance {
+                daylight: match transition.transition {
+                    IcalTimeZoneTransitionType::DAYLIGHT => true,
+                    IcalTimeZoneTransitionType::STANDARD => false,
+                },
+                dtstart: NaiveDateTime::default(),
+                offset_from: Duration::zero(),
+                offset_to: Duration::zero(),
+                rrule: None,
+                rdates: vec![],
+            };
+            for property in &transition.properties {
+                let value = property.value.clone().unwrap_or_default();
+                let context = |err: EventError| err.in_property(&property.name, &value, &tzid);
+                match property.name.as_ref() {
+                    "DTSTART" => observance.dtstart = parse_naive(&value).map_err(context)?,
+                    "TZOFFSETFROM" => observance.offset_from = parse_offset(&value).map_err(context)?,
+                    "TZOFFSETTO" => observance.offset_to = parse_offset(&value).map_err(context)?,
+                    "RRULE" => {
+                        let mut rrule = Rrule::new();
+                        for entry in value.split(';') {
+                            let p: Vec<&str> = entry.splitn(2, '=').collect();
+                            if p.len() == 2 {
+                                rrule.set_param(p[0], p[1]).map_err(context)?;
+                            }
+                        }
+                        observance.rrule = Some(rrule);
+                    }
+                    "RDATE" => {
+                        for date in value.split(',') {
+                            observance.rdates.push(parse_naive(date).map_err(context)?);
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            observances.push(observance);
+        }
+        observances.sort_by_key(|o| o.dtstart);
+
+        Ok(Timezone { tzid, observances })
+    }
+
+    // The definition of a zone of the tz database, with its transitions from
+    // the year from on. The transitions of the last years that fall on the
+    // same weekday of the month every year become yearly rules, so they go
+    // on after the years that are looked at.
+    pub fn from_tz(tz: Tz, from: i32) -> Self {
+        let start = NaiveDate::from_ymd_opt(from, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        let last_year = Local::now().year().max(from) + 1;
+        let end = NaiveDate::from_ymd_opt(last_year + 1, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        let state = |utc: &NaiveDateTime| {
+            let offset = tz.offset_from_utc_datetime(utc);
+            (Duration::seconds(i64::from(offset.fix().local_minus_utc())), !offset.dst_offset().is_zero())
+        };
+
+        let (offset, daylight) = state(&start);
+        let mut observances = vec![Observance {
+            daylight,
+            dtstart: start + offset,
+            offset_from: offset,
+            offset_to: offset,
+            rrule: None,
+            rdates: vec![],
+        }];
+        // zones don't change more than once a day, look for the second of
+        // the change in the days it happens
+        let mut day = start;
+        let mut before = state(&day);
+        while day < end {
+            let next = day + Duration::days(1);
+            let after = state(&next);
+            if after != before {
+                let (mut low, mut high) = (day, next);
+                while high - low > Duration::seconds(1) {
+                    let middle = low + (high - low) / 2;
+                    if state(&middle) == before {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+                observances.push(Observance {
+                    daylight: after.1,
+                    dtstart: high + before.0,
+                    offset_from: before.0,
+                    offset_to: after.0,
+                    rrule: None,
+                    rdates: vec![],
+                });
+            }
+            day = next;
+            before = after;
+        }
+
+        // the changes of the last year that repeat at least the year before
+        // are replaced by a rule from the first year they happen like that
+        let changes: Vec<Observance> = observances
+            .iter()
+            .filter(|o| o.dtstart.year() == last_year && o.offset_from != o.offset_to)
+            .cloned()
+            .collect();
+        for change in changes {
+            let same = |o: &Observance, rule: YearlyRule| {
+                o.offset_from == change.offset_from && o.offset_to == change.offset_to &&
+                    o.daylight == change.daylight && yearly_rule(o, rule.1 < 0) == Some(rule)
+            };
+            // a change on the 4th sunday can be the last one too, take the
+            // way that holds for more years
+            let first_year = |rule: YearlyRule| {
+                let mut first = last_year;
+                while observances.iter().any(|o| o.dtstart.year() == first - 1 && same(o, rule)) {
+                    first -= 1;
+                }
+                first
+            };
+            let (rule, first) = match (yearly_rule(&change, false), yearly_rule(&change, true)) {
+                (Some(nth), Some(last)) if first_year(nth) < first_year(last) => (nth, first_year(nth)),
+                (_, Some(last)) => (last, first_year(last)),
+                (Some(nth), None) => (nth, first_year(nth)),
+                (None, None) => continue,
+            };
+            if first == last_year {
+                continue;
+            }
+            let dtstart = observances
+                .iter()
+                .find(|o| o.dtstart.year() == first && same(o, rule))
+                .map(|o| o.dtstart)
+                .unwrap();
+            observances.retain(|o| o.dtstart.year() < first || !same(o, rule));
+            let (month, nth, weekday, _) = rule;
+            let mut rrule = Rrule::new();
+            rrule.freq = Freq::Yearly;
+            rrule.bymonth = vec![month];
+            rrule.byday = vec![(nth, weekday)];
+            observances.push(Observance { dtstart, rrule: Some(rrule), ..change });
+        }
+        observances.sort_by_key(|o| o.dtstart);
+
+        Timezone { tzid: tz.name().to_string(), observances }
+    }
+
+    pub fn is_daylight(&self, utc: &NaiveDateTime) -> bool {
+        self.observance_at(utc).map(|o| o.daylight).unwrap_or(false)
+    }
+
+    pub fn offset_at(&self, utc: &NaiveDateTime) -> Duration {
+        match self.observance_at(utc) {
+            Some(observance) => observance.offset_to,
+            None => self.observances.first().map(|o| o.offset_from).unwrap_or_else(Duration::zero),
+        }
+    }
+
+    // Ambiguous local times take the first instant, the ones in a gap are
+    // shifted forward with the offset before the gap.
+    pub fn from_local(&self, local: &NaiveDateTime) -> Date {
+        let mut offsets: Vec<Duration> = self.observances
+            .iter()
+            .flat_map(|o| vec![o.offset_from, o.offset_to])
+            .collect();
+        offsets.sort();
+        offsets.dedup();
+
+        let offset = offsets
+            .into_iter()
+            .rev()
+            .find(|offset| self.offset_at(&(*local - *offset)) == *offset)
+            .unwrap_or_else(|| self.offset_at(&(*local - Duration::days(1))));
+        Date::Time(UTC.from_utc_datetime(&(*local - offset)))
+    }
+
+    pub fn to_local(&self, date: &Date) -> NaiveDateTime {
+        match *date {
+            Date::Time(t) => {
+                let utc = t.naive_utc();
+                utc + self.offset_at(&utc)
+            }
+            _ => date.naive(),
+        }
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut Writer<W>) -> io::Result<()> {
+        writer.begin("VTIMEZONE")?;
+        writer.property("TZID", &[], &self.tzid)?;
+        for observance in &self.observances {
+            let component = if observance.daylight { "DAYLIGHT" } else { "STANDARD" };
+            writer.begin(component)?;
+            writer.property("DTSTART", &[], &observance.dtstart.format("%Y%m%dT%H%M%S").to_string())?;
+            writer.property("TZOFFSETFROM", &[], &offset_value(observance.offset_from))?;
+            writer.property("TZOFFSETTO", &[], &offset_value(observance.offset_to))?;
+            if let Some(ref rrule) = observance.rrule {
+                writer.property("RRULE", &[], &rrule.to_string())?;
+            }
+            for rdate in &observance.rdates {
+                writer.property("RDATE", &[], &rdate.format("%Y%m%dT%H%M%S").to_string())?;
+            }
+            writer.end(component)?;
+        }
+        writer.end("VTIMEZONE")
+    }
+
+    fn observance_at(&self, utc: &NaiveDateTime) -> Option<&Observance> {
+        self.observances
+            .iter()
+            .filter_map(|o| o.last_onset(utc).map(|onset| (onset, o)))
+            .max_by_key(|(onset, _)| *onset)
+            .map(|(_, o)| o)
+    }
+}
+
+impl Observance {
+    // Latest onset of the observance in UTC that is not after utc
+    fn last_onset(&self, utc: &NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut last = None;
+        let mut update = |local: &NaiveDateTime| {
+            let onset = *local - self.offset_from;
+            if onset <= *utc && last.is_none_or(|last| onset > last) {
+                last = Some(onset);
+            }
+            onset <= *utc
+        };
+
+        match self.rrule {
+            Some(ref rrule) => {
+                let until = rrule.until.map(|until| until.naive());
+                // yearly rules repeat every year, no need to walk from the 1600s
+                let jump_year = utc.year() - 2;
+                let jump = rrule.freq == Freq::Yearly && rrule.interval == 1 && rrule.count.is_none() &&
+                    jump_year > self.dtstart.year();
+                let dtstart = if jump {
+                    self.dtstart.with_year(jump_year).unwrap_or(self.dtstart)
+                } else {
+                    self.dtstart
+                };
+                for local in rrule.iter(dtstart).skip(if jump { 1 } else { 0 }) {
+                    if until.is_some_and(|until| local - self.offset_from > until) || !update(&local) {
+                        break;
+                    }
+                }
+            }
+            None => {
+                update(&self.dtstart);
+            }
+        }
+        for rdate in &self.rdates {
+            update(rdate);
+        }
+        last
+    }
+}
+
+// The month, the weekday of the month and the time of the start of an
+// observance
+type YearlyRule = (u32, i32, Weekday, NaiveTime);
+
+// Counted from the end only the last weekday of the month, -1
+fn yearly_rule(observance: &Observance, from_end: bool) -> Option<YearlyRule> {
+    let date = observance.dtstart.date();
+    let nth = if !from_end {
+        ((date.day() - 1) / 7 + 1) as i32
+    } else if date.day() + 7 > days_in_month(date.year(), date.month()) {
+        -1
+    } else {
+        return None;
+    };
+    Some((date.month(), nth, date.weekday(), observance.dtstart.time()))
+}
+
+fn parse_naive(value: &str) -> Result<NaiveDateTime, EventError> {
+    let pattern = if value.ends_with('Z') {
+        "%Y%m%dT%H%M%SZ"
+    } else {
+        "%Y%m%dT%H%M%S"
+    };
+    NaiveDateTime::parse_from_str(value, pattern).map_err(|_| EventError::DateError(value.to_string()))
+}
+
+fn parse_offset(value: &str) -> Result<Duration, EventError> {
+    if value.len() < 5 {
+        return Err(EventError::DateError(value.to_string()));
+    }
+    let hours: i64 = value[1..3].parse()?;
+    let minutes: i64 = value[3..5].parse()?;
+    let seconds: i64 = if value.len() >= 7 {
+        value[5..7].parse()?
+    } else {
+        0
+    };
+    let offset = Duration::seconds(hours * 3600 + minutes * 60 + seconds);
+    match &value[..1] {
+        "-" => Ok(-offset),
+        _ => Ok(offset),
+    }
+}
+
+fn offset_value(offset: Duration) -> String {
+    let sign = if offset < Duration::zero() { '-' } else { '+' };
+    let seconds = offset.num_seconds().abs();
+    let mut value = format!("{}{:02}{:02}", sign, seconds / 3600, seconds % 3600 / 60);
+    if seconds % 60 != 0 {
+        value += &format!("{:02}", seconds % 60);
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use calendar::Calendar;
+
+    const ICS: &str = "BEGIN:VCALENDAR\r
+BEGIN:VTIMEZONE\r
+TZID:GMT +0100 (Standard) / GMT +0200 (Daylight)\r
+BEGIN:STANDARD\r
+DTSTART:16010101T030000\r
+TZOFFSETFROM:+0200\r
+TZOFFSETTO:+0100\r
+RRULE:FREQ=YEARLY;BYDAY=-1SU;BYMONTH=10\r
+END:STANDARD\r
+BEGIN:DAYLIGHT\r
+DTSTART:16010101T020000\r
+TZOFFSETFROM:+0100\r
+TZOFFSETTO:+0200\r
+RRULE:FREQ=YEARLY;BYDAY=-1SU;BYMONTH=3\r
+END:DAYLIGHT\r
+END:VTIMEZONE\r
+BEGIN:VEVENT\r
+DTSTART;TZID=\"GMT +0100 (Standard) / GMT +0200 (Daylight)\":20190320T090000\r
+DTEND;TZID=\"GMT +0100 (Standard) / GMT +0200 (Daylight)\":20190320T100000\r
+RRULE:FREQ=WEEKLY;UNTIL=20190410T090000;BYDAY=WE\r
+EXDATE;TZID=\"GMT +0100 (Standard) / GMT +0200 (Daylight)\":20190403T090000\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    #[test]
+    fn vtimezone() {
+        let calendar = Calendar::parse(ICS.as_bytes()).unwrap();
+        let starts: Vec<String> = calendar
+            .iter()
+            .map(|e| e.start.naive().format("%Y%m%dT%H%M").to_string())
+            .collect();
+        // UTC times, daylight saving starts on March 31st
+        assert_eq!(starts, vec!["20190320T0800", "20190327T0800", "20190410T0700"]);
+    }
+
+    #[test]
+    fn offsets() {
+        let calendar = Calendar::parse(ICS.as_bytes()).unwrap();
+        let tz = calendar.timezone("GMT +0100 (Standard) / GMT +0200 (Daylight)").unwrap();
+        let local = |s| ::chrono::NaiveDateTime::parse_from_str(s, "%Y%m%dT%H%M%S").unwrap();
+        // in the gap
+        assert_eq!(tz.from_local(&local("20190331T023000")).naive(), local("20190331T013000"));
+        // ambiguous, first instant
+        assert_eq!(tz.from_local(&local("20191027T023000")).naive(), local("20191027T003000"));
+        assert_eq!(tz.to_local(&tz.from_local(&local("20300101T120000"))), local("20300101T120000"));
+        assert_eq!(tz.to_local(&tz.from_local(&local("20300701T120000"))), local("20300701T120000"));
+    }
+}
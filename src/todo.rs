@@ -0,0 +1,176 @@
+use std::cmp::{Ordering, Ord};
+use std::fmt;
+use std::str::FromStr;
+
+use alarm::Alarm;
+use date::Date;
+use errors::EventError;
+use event::Property;
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Todo {
+    pub uid: String,
+    pub summary: String,
+    pub description: String,
+    pub start: Option<Date>,
+    pub due: Option<Date>,
+    pub completed: Option<Date>,
+    // 1 is the highest priority, 9 the lowest and 0 undefined
+    pub priority: u32,
+    pub percent_complete: u32,
+    // None when the todo has no STATUS, it is taken as needing action
+    pub status: Option<TodoStatus>,
+    pub alarms: Vec<Alarm>,
+    // the properties not above, RRULE included, kept to be written back
+    pub properties: Vec<Property>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Serialize, Deserialize)]
+pub enum TodoStatus {
+    NeedsAction,
+    InProcess,
+    Completed,
+    Canceled,
+}
+
+
+impl Todo {
+    pub fn new() -> Todo {
+        Todo {
+            uid: "".to_string(),
+            summary: "".to_string(),
+            description: "".to_string(),
+            start: None,
+            due: None,
+            completed: None,
+            priority: 0,
+            percent_complete: 0,
+            status: None,
+            alarms: vec![],
+            properties: vec![],
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        match self.status.unwrap_or(TodoStatus::NeedsAction) {
+            TodoStatus::NeedsAction | TodoStatus::InProcess => self.completed.is_none(),
+            TodoStatus::Completed | TodoStatus::Canceled => false,
+        }
+    }
+
+    // A todo due on a day is overdue from the next one, in the local time
+    // zone
+    pub fn is_overdue(&self, now: &Date) -> bool {
+        self.is_open() && self.due.is_some_and(|due| match due {
+            Date::AllDay(day) => day < now.local().date(),
+            _ => due < *now,
+        })
+    }
+}
+
+impl Default for Todo {
+    fn default() -> Self {
+        Todo::new()
+    }
+}
+
+impl fmt::Display for Todo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.due {
+            Some(due) => write!(f, "{:?}", due)?,
+            None => write!(f, "-")?,
+        }
+        write!(f, ": {}", self.summary)?;
+        if self.priority != 0 {
+            write!(f, " !{}", self.priority)?;
+        }
+        if self.percent_complete != 0 {
+            write!(f, " {}%", self.percent_complete)?;
+        }
+        Ok(())
+    }
+}
+
+// Sorted by due date, the ones without it last, and then by priority
+impl Ord for Todo {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let due = match (self.due, other.due) {
+            (Some(d1), Some(d2)) => d1.cmp(&d2),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        let priority = |todo: &Todo| if todo.priority == 0 { 10 } else { todo.priority };
+        due.then_with(|| priority(self).cmp(&priority(other)))
+            .then_with(|| self.summary.cmp(&other.summary))
+    }
+}
+
+impl PartialOrd for Todo {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for TodoStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            TodoStatus::NeedsAction => "NEEDS-ACTION",
+            TodoStatus::InProcess => "IN-PROCESS",
+            TodoStatus::Completed => "COMPLETED",
+            TodoStatus::Canceled => "CANCELLED",
+        })
+    }
+}
+
+impl FromStr for TodoStatus {
+    type Err = EventError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "NEEDS-ACTION" => Ok(TodoStatus::NeedsAction),
+            "IN-PROCESS" => Ok(TodoStatus::InProcess),
+            "COMPLETED" => Ok(TodoStatus::Completed),
+            "CANCELLED" => Ok(TodoStatus::Canceled),
+            _ => Err(EventError::StatusError(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Todo;
+    use date::Date;
+
+    #[test]
+    fn todo_ord() {
+        let mut todos = [Todo::new(), Todo::new(), Todo::new(), Todo::new()];
+        todos[0].summary = "no due".to_string();
+        todos[1].summary = "later".to_string();
+        todos[1].due = Some(Date::parse("20190110", "").unwrap());
+        todos[2].summary = "urgent".to_string();
+        todos[2].due = Some(Date::parse("20190101", "").unwrap());
+        todos[2].priority = 1;
+        todos[3].summary = "whenever".to_string();
+        todos[3].due = Some(Date::parse("20190101", "").unwrap());
+        todos.sort();
+        let summaries: Vec<&str> = todos.iter().map(|t| t.summary.as_str()).collect();
+        assert_eq!(summaries, vec!["urgent", "whenever", "later", "no due"]);
+    }
+
+    #[test]
+    fn todo_overdue() {
+        let mut todo = Todo::new();
+        todo.due = Some(Date::parse("20190101", "").unwrap());
+        // floating, so the days don't depend on the local time zone
+        assert!(todo.is_overdue(&Date::parse("20190102T000000", "").unwrap()));
+        assert!(!todo.is_overdue(&Date::parse("20190101T235900", "").unwrap()));
+        todo.due = Some(Date::parse("20190101T120000Z", "").unwrap());
+        assert!(todo.is_overdue(&Date::parse("20190101T120100Z", "").unwrap()));
+        assert!(!todo.is_overdue(&Date::parse("20190101T115900Z", "").unwrap()));
+        todo.percent_complete = 100;
+        todo.completed = Some(Date::parse("20190101T120000Z", "").unwrap());
+        assert!(!todo.is_overdue(&Date::parse("20190102T000000Z", "").unwrap()));
+    }
+}
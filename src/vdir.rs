@@ -0,0 +1,25 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// The .ics files of the directory, sorted. Hidden files are skipped, as
+// vdirsyncer uses them for its own state and partially written items.
+pub fn items(path: &Path, recursive: bool) -> io::Result<Vec<PathBuf>> {
+    let mut items = vec![];
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            if recursive {
+                items.extend(self::items(&path, recursive)?);
+            }
+        } else if path.extension().is_some_and(|ext| ext == "ics") {
+            items.push(path);
+        }
+    }
+    items.sort();
+    Ok(items)
+}
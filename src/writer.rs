@@ -0,0 +1,634 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Write;
+
+use chrono::Datelike;
+use chrono_tz::{Tz, UTC};
+
+use alarm::{Alarm, Trigger};
+use date::Date;
+use event::{Class, End, Event, Transp};
+use freebusy::FreeBusy;
+use journal::Journal;
+use periodic::{Periodic, Override};
+use timezone::Timezone;
+use todo::Todo;
+
+// Content lines can't be longer than 75 octets, longer ones are folded
+const LINE_LEN: usize = 75;
+
+pub type Params = Vec<(String, Vec<String>)>;
+
+pub struct Writer<W: Write> {
+    out: W,
+    // zones of the tz database used by the dates written, with the first
+    // year they are used
+    zones: BTreeMap<String, (Tz, i32)>,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(out: W) -> Self {
+        Writer { out, zones: BTreeMap::new() }
+    }
+
+    // The definitions of the zones of the tz database in the dates written
+    // so far, each TZID needs a VTIMEZONE in the calendar
+    pub fn used_timezones(&self) -> Vec<Timezone> {
+        self.zones.values().map(|&(tz, year)| Timezone::from_tz(tz, year)).collect()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    pub fn begin(&mut self, component: &str) -> io::Result<()> {
+        self.property("BEGIN", &[], component)
+    }
+
+    pub fn end(&mut self, component: &str) -> io::Result<()> {
+        self.property("END", &[], component)
+    }
+
+    pub fn property(&mut self, name: &str, params: &[(String, Vec<String>)], value: &str) -> io::Result<()> {
+        let mut line = name.to_string();
+        for (param, values) in params {
+            let values: Vec<String> = values.iter().map(|v| quote(v)).collect();
+            line += &format!(";{}={}", param, values.join(","));
+        }
+        line.push(':');
+        line += value;
+        self.fold(&line)
+    }
+
+    pub fn text(&mut self, name: &str, value: &str) -> io::Result<()> {
+        if value.is_empty() {
+            return Ok(());
+        }
+        self.property(name, &[], &escape(value))
+    }
+
+    // The UID is kept as it was read, without escaping
+    fn uid(&mut self, uid: &str) -> io::Result<()> {
+        if uid.is_empty() {
+            return Ok(());
+        }
+        self.property("UID", &[], uid)
+    }
+
+    pub fn date(&mut self, name: &str, date: &Date) -> io::Result<()> {
+        let (params, value) = self.local_value(date, None);
+        self.property(name, &params, &value)
+    }
+
+    pub fn event(&mut self, event: &Event) -> io::Result<()> {
+        self.event_with(event, None, |_| Ok(()))
+    }
+
+    // The dates of rules in a custom time zone are written in its wall
+    // time, so they expand the same when read back
+    pub fn periodic(&mut self, periodic: &Periodic) -> io::Result<()> {
+        let timezone = periodic.timezone.as_ref();
+        self.event_with(&periodic.event, timezone, |w| {
+            if let Some(ref rrule) = periodic.rrule {
+                w.property("RRULE", &[], &rrule.to_string())?;
+            }
+            for rdate in &periodic.rdates {
+                let (mut params, mut value) = w.local_value(&rdate.start, timezone);
+                if let Some(end) = rdate.end {
+                    params.push(param("VALUE", "PERIOD"));
+                    value += "/";
+                    value += &match end {
+                        End::Date(end) => w.local_value(&end, timezone).1,
+                        End::Duration(duration) => duration_value(duration),
+                    };
+                }
+                w.property("RDATE", &params, &value)?;
+            }
+            for exdate in &periodic.exdates {
+                let (params, value) = w.local_value(exdate, timezone);
+                w.property("EXDATE", &params, &value)?;
+            }
+            Ok(())
+        })?;
+
+        for o in &periodic.overrides {
+            self.instance(o, timezone)?;
+        }
+        Ok(())
+    }
+
+    // An instance of a series, with the RECURRENCE-ID it overrides
+    pub fn instance(&mut self, o: &Override, timezone: Option<&Timezone>) -> io::Result<()> {
+        self.event_with(&o.event, timezone, |w| {
+            let (mut params, value) = w.local_value(&o.recurrence_id, timezone);
+            if o.this_and_future {
+                params.push(param("RANGE", "THISANDFUTURE"));
+            }
+            w.property("RECURRENCE-ID", &params, &value)
+        })
+    }
+
+    fn event_with<F>(&mut self, event: &Event, timezone: Option<&Timezone>, extra: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut Self) -> io::Result<()>,
+    {
+        self.begin("VEVENT")?;
+        self.uid(&event.uid)?;
+        if event.property("DTSTAMP").is_none() {
+            self.date("DTSTAMP", &Date::now())?;
+        }
+        let (params, value) = self.local_value(&event.start, timezone);
+        self.property("DTSTART", &params, &value)?;
+        match event.end {
+            End::Date(end) => {
+                let (params, value) = self.local_value(&end, timezone);
+                self.property("DTEND", &params, &value)?;
+            }
+            End::Duration(duration) => self.property("DURATION", &[], &duration_value(duration))?,
+        }
+        extra(self)?;
+        for &(name, text) in &[
+            ("SUMMARY", &event.summary),
+            ("LOCATION", &event.location),
+            ("DESCRIPTION", &event.description),
+        ] {
+            if text.is_empty() {
+                continue;
+            }
+            let params = event.text_params
+                .iter()
+                .find(|(property, _)| property == name)
+                .map_or(&[][..], |(_, params)| params);
+            self.property(name, params, &escape(text))?;
+        }
+        if let Some(status) = event.status {
+            self.property("STATUS", &[], &status.to_string())?;
+        }
+        if let Some(ref organizer) = event.organizer {
+            let mut params: Params = if organizer.name.is_empty() {
+                vec![]
+            } else {
+                vec![param("CN", &organizer.name)]
+            };
+            params.extend(organizer.params.iter().cloned());
+            self.property("ORGANIZER", &params, &organizer.address)?;
+        }
+        for attendee in &event.attendees {
+            let mut params = vec![];
+            if !attendee.name.is_empty() {
+                params.push(param("CN", &attendee.name));
+            }
+            params.push(param("ROLE", &attendee.role.to_string()));
+            params.push(param("PARTSTAT", &attendee.partstat.to_string()));
+            if attendee.rsvp {
+                params.push(param("RSVP", "TRUE"));
+            }
+            params.extend(attendee.params.iter().cloned());
+            self.property("ATTENDEE", &params, &attendee.address)?;
+        }
+        if !event.categories.is_empty() {
+            let categories: Vec<String> = event.categories.iter().map(|c| escape(c)).collect();
+            self.property("CATEGORIES", &[], &categories.join(","))?;
+        }
+        if !event.url.is_empty() {
+            self.property("URL", &[], &event.url)?;
+        }
+        if event.class != Class::Public {
+            self.property("CLASS", &[], &event.class.to_string())?;
+        }
+        if event.transp != Transp::Opaque {
+            self.property("TRANSP", &[], &event.transp.to_string())?;
+        }
+        if event.priority != 0 {
+            self.property("PRIORITY", &[], &event.priority.to_string())?;
+        }
+        if event.sequence != 0 {
+            self.property("SEQUENCE", &[], &event.sequence.to_string())?;
+        }
+        if let Some(ref created) = event.created {
+            self.date("CREATED", created)?;
+        }
+        if let Some(ref last_modified) = event.last_modified {
+            self.date("LAST-MODIFIED", last_modified)?;
+        }
+        for property in &event.properties {
+            self.property(&property.name, &property.params, &property.value)?;
+        }
+        for alarm in &event.alarms {
+            self.alarm(alarm)?;
+        }
+        self.end("VEVENT")
+    }
+
+    pub fn alarm(&mut self, alarm: &Alarm) -> io::Result<()> {
+        self.begin("VALARM")?;
+        self.property("ACTION", &[], &alarm.action.to_string())?;
+        match alarm.trigger {
+            Trigger::Relative { offset, related_end } => {
+                let params = if related_end {
+                    vec![param("RELATED", "END")]
+                } else {
+                    vec![]
+                };
+                self.property("TRIGGER", &params, &duration_value(offset))?;
+            }
+            Trigger::Absolute(date) => {
+                let (mut params, value) = self.local_value(&date, None);
+                params.push(param("VALUE", "DATE-TIME"));
+                self.property("TRIGGER", &params, &value)?;
+            }
+        }
+        if alarm.repeat != 0 {
+            self.property("REPEAT", &[], &alarm.repeat.to_string())?;
+            self.property("DURATION", &[], &duration_value(alarm.interval))?;
+        }
+        self.text("DESCRIPTION", &alarm.description)?;
+        self.end("VALARM")
+    }
+
+    pub fn todo(&mut self, todo: &Todo) -> io::Result<()> {
+        self.begin("VTODO")?;
+        self.uid(&todo.uid)?;
+        if !todo.properties.iter().any(|p| p.name == "DTSTAMP") {
+            self.date("DTSTAMP", &Date::now())?;
+        }
+        for &(name, date) in &[("DTSTART", todo.start), ("DUE", todo.due), ("COMPLETED", todo.completed)] {
+            if let Some(date) = date {
+                self.date(name, &date)?;
+            }
+        }
+        self.text("SUMMARY", &todo.summary)?;
+        self.text("DESCRIPTION", &todo.description)?;
+        if let Some(status) = todo.status {
+            self.property("STATUS", &[], &status.to_string())?;
+        }
+        if todo.priority != 0 {
+            self.property("PRIORITY", &[], &todo.priority.to_string())?;
+        }
+        if todo.percent_complete != 0 {
+            self.property("PERCENT-COMPLETE", &[], &todo.percent_complete.to_string())?;
+        }
+        for property in &todo.properties {
+            self.property(&property.name, &property.params, &property.value)?;
+        }
+        for alarm in &todo.alarms {
+            self.alarm(alarm)?;
+        }
+        self.end("VTODO")
+    }
+
+    pub fn journal(&mut self, journal: &Journal) -> io::Result<()> {
+        self.begin("VJOURNAL")?;
+        self.uid(&journal.uid)?;
+        if !journal.properties.iter().any(|p| p.name == "DTSTAMP") {
+            self.date("DTSTAMP", &Date::now())?;
+        }
+        if let Some(ref start) = journal.start {
+            self.date("DTSTART", start)?;
+        }
+        self.text("SUMMARY", &journal.summary)?;
+        self.text("DESCRIPTION", &journal.description)?;
+        for property in &journal.properties {
+            self.property(&property.name, &property.params, &property.value)?;
+        }
+        self.end("VJOURNAL")
+    }
+
+    pub fn free_busy(&mut self, free_busy: &FreeBusy) -> io::Result<()> {
+        self.begin("VFREEBUSY")?;
+        self.uid(&free_busy.uid)?;
+        self.date("DTSTAMP", &Date::now())?;
+        if !free_busy.organizer.is_empty() {
+            self.property("ORGANIZER", &[], &free_busy.organizer)?;
+        }
+        for &(name, date) in &[("DTSTART", free_busy.start), ("DTEND", free_busy.end)] {
+            if let Some(date) = date {
+                self.date(name, &utc(&date))?;
+            }
+        }
+        for busy in &free_busy.periods {
+            let value = format!("{}/{}", date_value(&utc(&busy.start)).1, date_value(&utc(&busy.end)).1);
+            self.property("FREEBUSY", &[param("FBTYPE", &busy.kind.to_string())], &value)?;
+        }
+        self.end("VFREEBUSY")
+    }
+
+    // Dates in a custom zone go in its wall time, the zones of the tz
+    // database are kept to define them
+    fn local_value(&mut self, date: &Date, timezone: Option<&Timezone>) -> (Params, String) {
+        match (timezone, *date) {
+            (Some(tz), Date::Time(_)) => (
+                vec![param("TZID", &tz.tzid)],
+                tz.to_local(date).format("%Y%m%dT%H%M%S").to_string(),
+            ),
+            (None, Date::Time(t)) if t.timezone() != UTC => {
+                let year = t.naive_local().year();
+                let zone = self.zones.entry(t.timezone().name().to_string()).or_insert((t.timezone(), year));
+                zone.1 = zone.1.min(year);
+                date_value(date)
+            }
+            _ => date_value(date),
+        }
+    }
+
+    fn fold(&mut self, line: &str) -> io::Result<()> {
+        let mut len = 0;
+        for c in line.chars() {
+            if len + c.len_utf8() > LINE_LEN {
+                self.out.write_all(b"\r\n ")?;
+                len = 1;
+            }
+            write!(self.out, "{}", c)?;
+            len += c.len_utf8();
+        }
+        self.out.write_all(b"\r\n")
+    }
+}
+
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped += "\\\\",
+            ';' => escaped += "\\;",
+            ',' => escaped += "\\,",
+            '\n' => escaped += "\\n",
+            '\r' => (),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') | Some('N') => unescaped.push('\n'),
+                Some(c) => unescaped.push(c),
+                None => unescaped.push('\\'),
+            },
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+fn quote(value: &str) -> String {
+    if value.contains([':', ';', ',']) {
+        format!("\"{}\"", value.replace('"', "'"))
+    } else {
+        value.to_string()
+    }
+}
+
+fn param(name: &str, value: &str) -> (String, Vec<String>) {
+    (name.to_string(), vec![value.to_string()])
+}
+
+pub fn date_value(date: &Date) -> (Params, String) {
+    match *date {
+        Date::Time(t) if t.timezone() == UTC => (vec![], t.format("%Y%m%dT%H%M%SZ").to_string()),
+        Date::Time(t) => (
+            vec![param("TZID", t.timezone().name())],
+            t.format("%Y%m%dT%H%M%S").to_string(),
+        ),
+        Date::Floating(t) => (vec![], t.format("%Y%m%dT%H%M%S").to_string()),
+        Date::AllDay(d) => (vec![param("VALUE", "DATE")], d.format("%Y%m%d").to_string()),
+    }
+}
+
+
+fn utc(date: &Date) -> Date {
+    match *date {
+        Date::Time(t) => Date::Time(t.with_timezone(&UTC)),
+        _ => *date,
+    }
+}
+
+pub fn duration_value(duration: ::chrono::Duration) -> String {
+    let sign = if duration < ::chrono::Duration::zero() { "-" } else { "" };
+    let mut seconds = duration.num_seconds().abs();
+    if seconds == 0 {
+        return "PT0S".to_string();
+    }
+    let mut value = format!("{}P", sign);
+    let days = seconds / 86_400;
+    seconds %= 86_400;
+    if days % 7 == 0 && seconds == 0 {
+        return format!("{}{}W", value, days / 7);
+    }
+    if days != 0 {
+        value += &format!("{}D", days);
+    }
+    if seconds != 0 {
+        value.push('T');
+        for &(unit, len) in &[('H', 3600), ('M', 60), ('S', 1)] {
+            if seconds >= len {
+                value += &format!("{}{}", seconds / len, unit);
+                seconds %= len;
+            }
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Writer, escape, unescape};
+    use calendar::Calendar;
+    use date::Date;
+    use event::Event;
+
+    const ICS: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VTIMEZONE\r
+TZID:Custom\r
+BEGIN:STANDARD\r
+DTSTART:16010101T030000\r
+TZOFFSETFROM:+0200\r
+TZOFFSETTO:+0100\r
+RRULE:FREQ=YEARLY;BYDAY=-1SU;BYMONTH=10\r
+END:STANDARD\r
+BEGIN:DAYLIGHT\r
+DTSTART:16010101T020000\r
+TZOFFSETFROM:+0100\r
+TZOFFSETTO:+0200\r
+RRULE:FREQ=YEARLY;BYDAY=-1SU;BYMONTH=3\r
+END:DAYLIGHT\r
+END:VTIMEZONE\r
+BEGIN:VEVENT\r
+UID:weekly\r
+DTSTAMP:20190101T000000Z\r
+DTSTART;TZID=Custom:20190320T090000\r
+DTEND;TZID=Custom:20190320T100000\r
+RRULE:FREQ=WEEKLY;UNTIL=20190501T090000Z;BYDAY=WE,FR;WKST=SU\r
+EXDATE;TZID=Custom:20190403T090000\r
+RDATE;VALUE=PERIOD:20190402T120000Z/PT30M\r
+SUMMARY:Weekly\\, with a comma; and a very long summary that has to be folded in more than one line\r
+DESCRIPTION:first line\\nsecond line with ñ and ü\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+TRIGGER:-PT15M\r
+DESCRIPTION:soon\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:weekly\r
+DTSTAMP:20190101T000000Z\r
+RECURRENCE-ID;TZID=Custom:20190322T090000\r
+DTSTART:20190322T110000Z\r
+DURATION:PT2H\r
+SUMMARY:Moved\r
+CLASS:X-TEAM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:single\r
+DTSTAMP:20190101T000000Z\r
+DTSTART;TZID=America/New_York:20190325T090000\r
+DTEND;TZID=America/New_York:20190325T100000\r
+SUMMARY;LANGUAGE=en:Single\r
+DESCRIPTION;ALTREP=\"cid:part1@example.org\":See the agenda\r
+STATUS:TENTATIVE\r
+ORGANIZER;CN=\"Doe, Jane\";SENT-BY=\"mailto:sec@example.com\":mailto:jane@example.com\r
+ATTENDEE;CN=Bob;ROLE=CHAIR;PARTSTAT=ACCEPTED;RSVP=TRUE:mailto:bob@example.com\r
+ATTENDEE;CUTYPE=ROOM;DELEGATED-FROM=\"mailto:bob@example.com\";X-NUM=1,2:mailto:room@example.com\r
+CATEGORIES:work,a\\,b\r
+CLASS:PRIVATE\r
+TRANSP:TRANSPARENT\r
+SEQUENCE:2\r
+LAST-MODIFIED:20190301T100000Z\r
+X-GOOGLE-CONFERENCE:https://meet.example.com/abc\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:allday\r
+DTSTAMP:20190101T000000Z\r
+DTSTART;VALUE=DATE:20190326\r
+DTEND;VALUE=DATE:20190327\r
+RRULE:FREQ=YEARLY;COUNT=3\r
+SUMMARY:Floating friend\r
+END:VEVENT\r
+BEGIN:VTODO\r
+UID:todo\r
+DTSTAMP:20190101T000000Z\r
+DUE:20190330T100000\r
+PRIORITY:2\r
+STATUS:IN-PROCESS\r
+SUMMARY:Todo\r
+RRULE:FREQ=WEEKLY\r
+X-FOO;X-PARAM=1:bar\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+TRIGGER:-PT1H\r
+END:VALARM\r
+END:VTODO\r
+BEGIN:VJOURNAL\r
+UID:journal\r
+DTSTAMP:20190101T000000Z\r
+DTSTART;VALUE=DATE:20190321\r
+DESCRIPTION:notes\r
+END:VJOURNAL\r
+BEGIN:VJOURNAL\r
+UID:undated\r
+DTSTAMP:20190101T000000Z\r
+DESCRIPTION:someday\r
+X-BAR:baz\r
+END:VJOURNAL\r
+BEGIN:VFREEBUSY\r
+UID:room,1;a\r
+FREEBUSY;FBTYPE=BUSY-TENTATIVE:20190321T090000Z/PT1H\r
+END:VFREEBUSY\r
+END:VCALENDAR\r
+";
+
+    fn events(calendar: &Calendar) -> Vec<Event> {
+        calendar.iter().take(20).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let calendar = Calendar::parse(ICS.as_bytes()).unwrap();
+        let mut out = vec![];
+        calendar.write(&mut out).unwrap();
+        let written = String::from_utf8(out).unwrap();
+        let parsed = Calendar::parse(written.as_bytes()).unwrap();
+
+        assert_eq!(events(&calendar), events(&parsed));
+        assert_eq!(events(&calendar).len(), 17);
+        assert_eq!(calendar.todos(), parsed.todos());
+        assert_eq!(calendar.journals().collect::<Vec<_>>(), parsed.journals().collect::<Vec<_>>());
+        assert_eq!(calendar.free_busy(), parsed.free_busy());
+        assert!(written.contains("DTSTART;TZID=Custom:20190320T090000\r\n"));
+        assert!(written.contains("RRULE:FREQ=WEEKLY\r\nX-FOO;X-PARAM=1:bar\r\nBEGIN:VALARM\r\n"));
+        assert!(written.contains("UID:undated\r\n"));
+        assert!(written.contains("UID:room,1;a\r\n"));
+        let unfolded = written.replace("\r\n ", "");
+        assert!(unfolded.contains("SUMMARY;LANGUAGE=en:Single\r\n"));
+        assert!(unfolded.contains("DESCRIPTION;ALTREP=\"cid:part1@example.org\":See the agenda\r\n"));
+        assert!(unfolded.contains("SENT-BY=\"mailto:sec@example.com\":mailto:jane@example.com\r\n"));
+        assert!(unfolded.contains("CUTYPE=ROOM;DELEGATED-FROM=\"mailto:bob@example.com\";X-NUM=1,2:"));
+        assert!(written.contains("X-BAR:baz\r\n"));
+        assert!(written.contains("CLASS:X-TEAM\r\n"));
+        // only the components that had a STATUS have it
+        assert_eq!(written.matches("\r\nSTATUS:").count(), ICS.matches("\r\nSTATUS:").count());
+        assert!(written.contains("RRULE:FREQ=WEEKLY;UNTIL=20190501T090000Z;BYDAY=WE,FR;WKST=SU\r\n"));
+    }
+
+    #[test]
+    fn tz_database_zones() {
+        let ics = "BEGIN:VCALENDAR\r
+BEGIN:VEVENT\r
+UID:weekly\r
+DTSTART;TZID=Europe/Madrid:20190320T090000\r
+DTEND;TZID=Europe/Madrid:20190320T100000\r
+RRULE:FREQ=WEEKLY\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:single\r
+DTSTART;TZID=America/New_York:20210101T090000\r
+DURATION:PT1H\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+        let calendar = Calendar::parse(ics.as_bytes()).unwrap();
+        let mut out = vec![];
+        calendar.write(&mut out).unwrap();
+        let written = String::from_utf8(out).unwrap();
+        assert!(written.contains("BEGIN:VTIMEZONE\r\nTZID:America/New_York\r\n"));
+        assert!(written.contains("BEGIN:VTIMEZONE\r\nTZID:Europe/Madrid\r\n"));
+        assert!(written.contains("RRULE:FREQ=YEARLY;BYDAY=-1SU;BYMONTH=3\r\n"));
+        assert!(written.find("BEGIN:VTIMEZONE") < written.find("BEGIN:VEVENT"));
+
+        // read back the dates go through the VTIMEZONE, across the summer
+        // time changes and years after the ones written
+        let parsed = Calendar::parse(written.as_bytes()).unwrap();
+        for &(start, end) in &[("20190301", "20190501"), ("20211001", "20211201"), ("20400301", "20400501")] {
+            let start = Date::parse(start, "").unwrap();
+            let end = Date::parse(end, "").unwrap();
+            let starts = |c: &Calendar| c.between(start, end).map(|e| e.start.utc()).collect::<Vec<_>>();
+            assert_eq!(starts(&calendar), starts(&parsed));
+            assert!(!starts(&parsed).is_empty());
+        }
+    }
+
+    #[test]
+    fn folding() {
+        let summary = "ñ".repeat(100);
+        let mut writer = Writer::new(vec![]);
+        writer.text("SUMMARY", &summary).unwrap();
+        let written = String::from_utf8(writer.into_inner()).unwrap();
+        let lines: Vec<&str> = written.split("\r\n").collect();
+        assert!(lines.len() > 2);
+        assert!(lines.iter().all(|line| line.len() <= 75));
+        assert_eq!(written.replace("\r\n ", ""), format!("SUMMARY:{}\r\n", summary));
+    }
+
+    #[test]
+    fn escaping() {
+        let text = "a, b; c\\d\nnext";
+        assert_eq!(escape(text), "a\\, b\\; c\\\\d\\nnext");
+        assert_eq!(unescape(&escape(text)), text);
+    }
+}